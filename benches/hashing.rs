@@ -0,0 +1,140 @@
+// hashing throughput on a synthetic corpus of camera sized jpeg images: the hash before
+// decoding at reduced size, the current hash of fully decoded images and the current hash.
+// run with `cargo bench --no-default-features`, `BENCH_IMAGES=<N>` changes the size of the corpus
+use deduplicator::hasher::calc_hash;
+use deduplicator::hasher::hash_distance;
+use deduplicator::hasher::pool::HashPool;
+use deduplicator::hasher::try_image_hash;
+use deduplicator::index::ImageWrapper;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+// 24 megapixels
+const WIDTH: u32 = 6000;
+const HEIGHT: u32 = 4000;
+
+fn main() {
+    let count: usize = env::var("BENCH_IMAGES")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(16);
+    let root = env::temp_dir().join(format!("deduplicator-bench-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+
+    println!("Writing {} images of {}x{}", count, WIDTH, HEIGHT);
+    let paths: Vec<PathBuf> = (0..count).map(|x| write_image(&root, x)).collect();
+    let bytes: u64 = paths.iter().map(|x| fs::metadata(x).unwrap().len()).sum();
+
+    run(
+        "before: full decoding, 8x8 nearest",
+        &paths,
+        bytes,
+        baseline_hash,
+    );
+    let full = run("full decoding, 8x8 average", &paths, bytes, full_hash);
+    let scaled = run(
+        "after: scaled decoding, 8x8 average",
+        &paths,
+        bytes,
+        try_image_hash,
+    );
+
+    let changed = full
+        .iter()
+        .zip(scaled.iter())
+        .map(|(full, scaled)| hash_distance(*full, *scaled))
+        .max()
+        .unwrap_or(0);
+    println!(
+        "Hashes of scaled and full decoding differ by at most {} bits",
+        changed
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+// the same hash of the fully decoded image
+fn full_hash(path: &Path) -> Result<i64, String> {
+    image::open(path)
+        .map(calc_hash)
+        .map_err(|err| err.to_string())
+}
+
+// the hash of version 1: the fully decoded image is sampled at 8x8 pixels
+fn baseline_hash(path: &Path) -> Result<i64, String> {
+    let img = image::open(path).map_err(|err| err.to_string())?;
+    let img = img.resize_exact(8, 8, image::imageops::FilterType::Nearest);
+    let img = img.grayscale();
+
+    let light_medium = img.as_bytes().iter().map(|x| *x as u64).sum::<u64>() / 64;
+
+    let mut hash: i64 = 0;
+    for (i, pixel) in img.as_bytes().iter().enumerate() {
+        if *pixel as u64 > light_medium {
+            hash |= 1 << i;
+        }
+    }
+
+    Ok(hash)
+}
+
+// hashes by image in the order of paths
+fn run(
+    name: &str,
+    paths: &[PathBuf],
+    bytes: u64,
+    hash: fn(&Path) -> Result<i64, String>,
+) -> Vec<i64> {
+    let images = paths
+        .iter()
+        .enumerate()
+        .map(|(id, path)| ImageWrapper {
+            id: id as i64,
+            path: path.clone(),
+            hash: None,
+        })
+        .collect();
+
+    let pool = HashPool::default();
+    let started = Instant::now();
+    let mut hashed: Vec<(i64, i64)> = pool
+        .start(images, hash)
+        .iter()
+        .map(|(image, hash)| (image.id, hash.unwrap()))
+        .collect();
+    let elapsed = started.elapsed().as_secs_f64();
+    hashed.sort();
+
+    println!(
+        "{}: {} images in {:.2} s on {} threads, {:.1} images/s, {:.1} MB/s",
+        name,
+        paths.len(),
+        elapsed,
+        pool.threads(),
+        paths.len() as f64 / elapsed,
+        bytes as f64 / 1024.0 / 1024.0 / elapsed
+    );
+
+    hashed.into_iter().map(|(_, hash)| hash).collect()
+}
+
+// smooth shapes with some noise, like a photo compresses
+fn write_image(root: &Path, seed: usize) -> PathBuf {
+    let seed = seed as u32;
+    let image = image::RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729) ^ seed.wrapping_mul(31)) % 16;
+        image::Rgb([
+            ((x / 40 + seed * 17) % 240 + noise) as u8,
+            ((y / 30 + seed * 29) % 240 + noise) as u8,
+            (((x + y) / 50 + seed * 7) % 240 + noise) as u8,
+        ])
+    });
+
+    let path = root.join(format!("{}.jpg", seed));
+    image.save(&path).unwrap();
+
+    path
+}
@@ -0,0 +1,332 @@
+use crate::filesystem::is_correct_extension;
+use crate::hasher::get_image_data_hash;
+use flate2::read::GzDecoder;
+use image;
+use image::codecs::jpeg::JpegDecoder;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+// images inside archives have virtual paths: path of the archive, separator and
+// path inside the archive, for example `/backup/2020.zip!/DCIM/1.jpg`
+pub const SEPARATOR: &str = "!/";
+pub const EXTENSIONS: [&str; 4] = [".zip", ".tar", ".tar.gz", ".tgz"];
+
+// images are decoded from memory, bigger entries are skipped
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+pub fn is_archive(path: &Path) -> bool {
+    let path = path.as_os_str().as_bytes();
+    EXTENSIONS.iter().any(|x| path.ends_with(x.as_bytes()))
+}
+
+pub fn virtual_path(archive: &Path, inner: &Path) -> PathBuf {
+    let mut result = archive.as_os_str().to_os_string();
+    result.push(SEPARATOR);
+    result.push(inner.as_os_str());
+
+    PathBuf::from(result)
+}
+
+// archive and path inside it, `None` for files on disk
+pub fn split_virtual_path(path: &Path) -> Option<(&Path, &Path)> {
+    let bytes = path.as_os_str().as_bytes();
+    let separator = SEPARATOR.as_bytes();
+
+    (0..bytes.len())
+        .filter(|x| bytes[*x..].starts_with(separator))
+        .map(|x| {
+            (
+                Path::new(OsStr::from_bytes(&bytes[..x])),
+                Path::new(OsStr::from_bytes(&bytes[x + separator.len()..])),
+            )
+        })
+        .find(|(archive, _)| is_archive(archive))
+}
+
+pub fn is_virtual_path(path: &Path) -> bool {
+    split_virtual_path(path).is_some()
+}
+
+// the archive is read once, every image inside it is hashed in memory.
+// returns virtual paths of images with their hashes or reasons why they can not be decoded
+pub fn hash_images(
+    archive: &Path,
+    extensions: &[String],
+) -> io::Result<Vec<(PathBuf, Result<i64, String>)>> {
+    let mut result = Vec::new();
+
+    visit_entries(archive, |inner, size, reader| {
+        if is_correct_extension(&inner, extensions) && size <= MAX_ENTRY_SIZE {
+            let data = read_entry(reader, size)?;
+            result.push((virtual_path(archive, &inner), get_image_data_hash(&data)));
+        }
+        Ok(true)
+    })?;
+
+    Ok(result)
+}
+
+// virtual paths of images inside the archive, entries are listed without decoding
+pub fn list_images(archive: &Path, extensions: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+
+    visit_entries(archive, |inner, size, _| {
+        if is_correct_extension(&inner, extensions) && size <= MAX_ENTRY_SIZE {
+            result.push(virtual_path(archive, &inner));
+        }
+        Ok(true)
+    })?;
+
+    Ok(result)
+}
+
+// reads the given images of one archive in a single pass and passes their virtual paths and
+// content to `on_image` until it returns `false`. images which are not found are skipped
+pub fn read_images(
+    archive: &Path,
+    images: &HashSet<PathBuf>,
+    mut on_image: impl FnMut(PathBuf, Vec<u8>) -> bool,
+) -> io::Result<()> {
+    let mut left = images.len();
+
+    visit_entries(archive, |inner, size, reader| {
+        let path = virtual_path(archive, &inner);
+        if !images.contains(&path) {
+            return Ok(true);
+        }
+
+        let data = read_entry(reader, size)?;
+        left = left.saturating_sub(1);
+        Ok(on_image(path, data) && left > 0)
+    })
+}
+
+// content of the image with virtual path
+pub fn read_image(path: &Path) -> io::Result<Vec<u8>> {
+    let (archive, inner) = split_virtual_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not inside an archive", path.display()),
+        )
+    })?;
+
+    let mut result = None;
+    visit_entries(archive, |name, size, reader| {
+        if name != inner {
+            return Ok(true);
+        }
+        result = Some(read_entry(reader, size)?);
+        Ok(false)
+    })?;
+
+    result.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not found", path.display()),
+        )
+    })
+}
+
+// opens images on disk and inside archives
+pub fn open_image(path: &Path) -> image::ImageResult<image::DynamicImage> {
+    if is_virtual_path(path) {
+        image::load_from_memory(&read_image(path)?)
+    } else {
+        image::open(path)
+    }
+}
+
+// like `open_image`, but jpeg images are decoded at 1/2, 1/4 or 1/8 of their size
+// while they stay at least `size` pixels wide or high, which is much faster.
+// other formats are decoded fully
+pub fn open_image_scaled(path: &Path, size: u16) -> image::ImageResult<image::DynamicImage> {
+    if is_virtual_path(path) {
+        load_image_scaled(&read_image(path)?, size)
+    } else {
+        decode_scaled(image::io::Reader::open(path)?.with_guessed_format()?, size)
+    }
+}
+
+pub fn load_image_scaled(data: &[u8], size: u16) -> image::ImageResult<image::DynamicImage> {
+    decode_scaled(
+        image::io::Reader::new(io::Cursor::new(data)).with_guessed_format()?,
+        size,
+    )
+}
+
+fn decode_scaled<R: io::BufRead + io::Seek>(
+    reader: image::io::Reader<R>,
+    size: u16,
+) -> image::ImageResult<image::DynamicImage> {
+    if reader.format() != Some(image::ImageFormat::Jpeg) {
+        return reader.decode();
+    }
+
+    let mut decoder = JpegDecoder::new(reader.into_inner())?;
+    decoder.scale(size, size)?;
+    image::DynamicImage::from_decoder(decoder)
+}
+
+// calls `on_entry` with path, size and content of every file inside the archive
+// until it returns `false`
+fn visit_entries(
+    archive: &Path,
+    mut on_entry: impl FnMut(PathBuf, u64, &mut dyn Read) -> io::Result<bool>,
+) -> io::Result<()> {
+    let file = fs::File::open(archive)?;
+    let name = archive.as_os_str().as_bytes();
+
+    if name.ends_with(b".zip") {
+        let mut zip = zip::ZipArchive::new(file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        for index in 0..zip.len() {
+            let mut entry = match zip.by_index(index) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    log::warn!("Can not read entry of {}: {}", archive.display(), err);
+                    continue;
+                }
+            };
+            if entry.is_dir() {
+                continue;
+            }
+
+            let inner = inner_path(entry.name_raw());
+            let size = entry.size();
+            if !on_entry(inner, size, &mut entry)? {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let reader: Box<dyn Read> = if name.ends_with(b".tar") {
+        Box::new(file)
+    } else {
+        Box::new(GzDecoder::new(file))
+    };
+    let mut tar = tar::Archive::new(reader);
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            continue;
+        }
+
+        let inner = inner_path(&entry.path_bytes());
+        let size = entry.size();
+        if !on_entry(inner, size, &mut entry)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// tar archives often store paths as `./name`
+fn inner_path(name: &[u8]) -> PathBuf {
+    let mut name = name;
+    while let Some(rest) = name.strip_prefix(b"./").or_else(|| name.strip_prefix(b"/")) {
+        name = rest;
+    }
+
+    PathBuf::from(OsString::from_vec(name.to_vec()))
+}
+
+fn read_entry(reader: &mut dyn Read, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(size.min(MAX_ENTRY_SIZE) as usize);
+    reader.take(MAX_ENTRY_SIZE).read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+#[test]
+fn test_virtual_path() {
+    let path = virtual_path(Path::new("/a!/b.zip"), Path::new("c/1.jpg"));
+
+    assert_eq!(Path::new("/a!/b.zip!/c/1.jpg"), path);
+    assert_eq!(
+        Some((Path::new("/a!/b.zip"), Path::new("c/1.jpg"))),
+        split_virtual_path(&path)
+    );
+    assert_eq!(None, split_virtual_path(Path::new("/a!/b.jpg")));
+    assert!(is_archive(Path::new("/backup.tar.gz")));
+}
+
+#[test]
+fn test_read_archives() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let extensions = vec![".jpg".to_string()];
+
+    let zip_path = root.join("1.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+    for (name, data) in [("a/1.jpg", "zip"), ("notes.txt", "")] {
+        zip.start_file(name, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(data.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let tar_path = root.join("2.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(
+        fs::File::create(&tar_path).unwrap(),
+        flate2::Compression::default(),
+    );
+    let mut tar = tar::Builder::new(encoder);
+    for (name, data) in [("./b/2.jpg", "tar"), ("./3.jpg", "")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, name, data.as_bytes()).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let images = hash_images(&zip_path, &extensions).unwrap();
+    assert_eq!(1, images.len());
+    assert_eq!(virtual_path(&zip_path, Path::new("a/1.jpg")), images[0].0);
+    assert!(images[0].1.is_err());
+
+    let images: Vec<PathBuf> = hash_images(&tar_path, &extensions)
+        .unwrap()
+        .into_iter()
+        .map(|x| x.0)
+        .collect();
+    assert_eq!(
+        vec![
+            virtual_path(&tar_path, Path::new("b/2.jpg")),
+            virtual_path(&tar_path, Path::new("3.jpg"))
+        ],
+        images
+    );
+
+    let zip_image = virtual_path(&zip_path, Path::new("a/1.jpg"));
+    assert_eq!(b"zip".to_vec(), read_image(&zip_image).unwrap());
+    assert_eq!(b"tar".to_vec(), read_image(&images[0]).unwrap());
+    assert!(read_image(&virtual_path(&zip_path, Path::new("2.jpg"))).is_err());
+
+    assert_eq!(images, list_images(&tar_path, &extensions).unwrap());
+    let wanted = HashSet::from([
+        images[1].clone(),
+        virtual_path(&tar_path, Path::new("4.jpg")),
+    ]);
+    let mut read = Vec::new();
+    read_images(&tar_path, &wanted, |path, data| {
+        read.push((path, data));
+        true
+    })
+    .unwrap();
+    assert_eq!(vec![(images[1].clone(), Vec::new())], read);
+}
@@ -0,0 +1,586 @@
+use clap::Parser;
+use clap::Subcommand;
+use deduplicator::config;
+use deduplicator::index::format_timestamp;
+use deduplicator::index::AcquiredConnection;
+use deduplicator::index::Database;
+use deduplicator::index::FolderWrapper;
+use deduplicator::index::PlanActionWrapper;
+use deduplicator::report::build_report;
+use deduplicator::report::html::write_html_file;
+use deduplicator::report::write_report;
+use deduplicator::report::ReportFormat;
+use deduplicator::resolver::plan;
+use deduplicator::resolver::policy::Policy;
+use deduplicator::resolver::policy::PolicyRule;
+use deduplicator::resolver::ResolutionStrategy;
+use deduplicator::scanner;
+use deduplicator::scanner::ScanControl;
+use deduplicator::scanner::ScanFolderStatus;
+use deduplicator::scanner::ScanOptions;
+use deduplicator::watcher;
+use deduplicator::watcher::WatchStatus;
+use simple_logger::SimpleLogger;
+use std::cell::Cell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(
+    name = "deduplicator-cli",
+    about = "Find and remove duplicated images without GUI"
+)]
+struct Cli {
+    /// Database file, overrides DEDUPLICATOR_DATABASE and config file
+    #[arg(long, global = true)]
+    database: Option<PathBuf>,
+    /// Library to work with, overrides DEDUPLICATOR_LIBRARY
+    #[arg(long, global = true, value_parser = parse_library, conflicts_with = "database")]
+    library: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Add folders to search images in
+    AddFolder {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Print added folders with their image counts and last scan time
+    Folders,
+    /// Forget folder and its images, files on disk are not touched
+    RemoveFolder { path: PathBuf },
+    /// Skip folder during scans, its images are kept
+    DisableFolder { path: PathBuf },
+    /// Scan previously disabled folder again
+    EnableFolder { path: PathBuf },
+    /// Replace gitignore-style exclusion patterns of folder, no patterns clear them
+    Exclude {
+        path: PathBuf,
+        patterns: Vec<String>,
+    },
+    /// Find new images in added folders and calculate their hashes
+    Scan {
+        /// Walk into symlinked files and folders
+        #[arg(long)]
+        follow_symlinks: bool,
+        /// Do not walk into folders mounted from other devices
+        #[arg(long)]
+        same_filesystem: bool,
+        /// Add every name of a hardlinked file
+        #[arg(long)]
+        keep_hardlinks: bool,
+        /// Do not look for images inside ZIP and TAR archives
+        #[arg(long)]
+        skip_archives: bool,
+        /// Try again images which failed to decode too many times
+        #[arg(long)]
+        retry_failed: bool,
+        /// Number of images hashed at once, all cores by default
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        hash_threads: Option<u64>,
+    },
+    /// Follow changes in added folders and report new duplicates until interrupted
+    Watch,
+    /// Print groups of duplicated images
+    ListDuplicates,
+    /// Choose kept image of every duplicate group by rules and remove the others
+    Resolve {
+        /// Rule in order of priority: resolution, size, oldest, no-copy or folder:<path>
+        #[arg(
+            long = "rule",
+            value_parser = parse_rule,
+            default_values = ["resolution", "size", "oldest", "no-copy"]
+        )]
+        rules: Vec<PolicyRule>,
+        /// What happens with removed image: delete, hardlink, symlink or reflink
+        #[arg(long, value_parser = parse_strategy, default_value = "delete")]
+        strategy: ResolutionStrategy,
+        /// Remove files, without it the plan is only checked and stored
+        #[arg(long, visible_alias = "yes")]
+        apply: bool,
+        /// Use stored plan instead of building a new one
+        #[arg(long, conflicts_with_all = ["rules", "strategy"])]
+        plan: Option<i64>,
+    },
+    /// Write duplicate groups with sizes and dimensions of images
+    Report {
+        /// json, csv or html, guessed from output file extension by default
+        #[arg(long, value_parser = parse_format)]
+        format: Option<ReportFormat>,
+        /// File to write report to, standard output by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Folder for thumbnails of html report, they are embedded into the page by default
+        #[arg(long, requires = "output")]
+        thumbnails_dir: Option<PathBuf>,
+    },
+    /// Print database statistics
+    Stats,
+    /// Print images which can not be decoded with the reasons
+    Errors,
+    /// Print names of existing libraries
+    Libraries,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Warn)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let database_path = match cli.database {
+        Some(database) => database,
+        None => config::library_database_path(&cli.library.unwrap_or(config::current_library())),
+    };
+    let database = Database::open(&database_path).await;
+    database.migrate().await;
+    let mut connection = database.get_connection().await;
+
+    let result = match cli.command {
+        Command::AddFolder { paths } => add_folder(&mut connection, paths).await,
+        Command::Folders => folders(&mut connection).await,
+        Command::RemoveFolder { path } => remove_folder(&mut connection, path).await,
+        Command::DisableFolder { path } => set_folder_enabled(&mut connection, path, false).await,
+        Command::EnableFolder { path } => set_folder_enabled(&mut connection, path, true).await,
+        Command::Exclude { path, patterns } => exclude(&mut connection, path, patterns).await,
+        Command::Scan {
+            follow_symlinks,
+            same_filesystem,
+            keep_hardlinks,
+            skip_archives,
+            retry_failed,
+            hash_threads,
+        } => {
+            // flags can only change defaults from the config file
+            let mut options = ScanOptions::load();
+            options.follow_symlinks |= follow_symlinks;
+            options.same_filesystem |= same_filesystem;
+            options.dedupe_hardlinks &= !keep_hardlinks;
+            options.scan_archives &= !skip_archives;
+            options.retry_failed = retry_failed;
+            if let Some(hash_threads) = hash_threads {
+                options.hash_threads = hash_threads as usize;
+            }
+            scan(&mut connection, &options).await
+        }
+        Command::Watch => watch(&mut connection).await,
+        Command::ListDuplicates => list_duplicates(&mut connection).await,
+        Command::Resolve {
+            rules,
+            strategy,
+            apply,
+            plan,
+        } => resolve(&mut connection, rules, strategy, apply, plan).await,
+        Command::Report {
+            format,
+            output,
+            thumbnails_dir,
+        } => report(&mut connection, format, output, thumbnails_dir).await,
+        Command::Stats => stats(&mut connection).await,
+        Command::Errors => errors(&mut connection).await,
+        Command::Libraries => libraries(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn add_folder(
+    connection: &mut AcquiredConnection,
+    paths: Vec<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let mut absolute_paths = Vec::new();
+
+    for path in paths {
+        match fs::canonicalize(&path) {
+            Ok(absolute_path) => absolute_paths.push(absolute_path),
+            Err(err) => eprintln!("Can not add {}: {}", path.display(), err),
+        }
+    }
+
+    for folder in connection.insert_folders(absolute_paths).await {
+        println!("Added {}", folder.path.display());
+    }
+
+    Ok(())
+}
+
+async fn folders(connection: &mut AcquiredConnection) -> Result<(), Box<dyn Error>> {
+    for folder in connection.get_all_folders().await? {
+        println!(
+            "{} {} images, {}{}",
+            folder.path.display(),
+            folder.images_count,
+            folder
+                .last_scan
+                .map(|x| format!("scanned {}", format_timestamp(x)))
+                .unwrap_or("never scanned".to_string()),
+            if folder.enabled { "" } else { " (disabled)" }
+        );
+
+        for pattern in folder.excludes.iter() {
+            println!("  exclude {}", pattern);
+        }
+    }
+
+    Ok(())
+}
+
+async fn remove_folder(
+    connection: &mut AcquiredConnection,
+    path: PathBuf,
+) -> Result<(), Box<dyn Error>> {
+    let folder = find_folder(connection, &path).await?;
+    connection.remove_folder(folder.id).await?;
+    println!("Removed {}", folder.path.display());
+
+    Ok(())
+}
+
+async fn set_folder_enabled(
+    connection: &mut AcquiredConnection,
+    path: PathBuf,
+    enabled: bool,
+) -> Result<(), Box<dyn Error>> {
+    let folder = find_folder(connection, &path).await?;
+    connection.set_folder_enabled(folder.id, enabled).await?;
+    println!(
+        "{} {}",
+        if enabled { "Enabled" } else { "Disabled" },
+        folder.path.display()
+    );
+
+    Ok(())
+}
+
+async fn exclude(
+    connection: &mut AcquiredConnection,
+    path: PathBuf,
+    patterns: Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    let folder = find_folder(connection, &path).await?;
+    connection.set_folder_excludes(folder.id, &patterns).await?;
+    println!(
+        "{} exclusion patterns for {}",
+        patterns.len(),
+        folder.path.display()
+    );
+
+    Ok(())
+}
+
+// folder may be given as stored or relative to the current directory, it may not exist anymore
+async fn find_folder(
+    connection: &mut AcquiredConnection,
+    path: &Path,
+) -> Result<FolderWrapper, Box<dyn Error>> {
+    let absolute_path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+    connection
+        .get_all_folders()
+        .await?
+        .into_iter()
+        .find(|x| x.path == absolute_path || x.path == path)
+        .ok_or(format!("{} is not added", path.display()).into())
+}
+
+async fn scan(
+    connection: &mut AcquiredConnection,
+    options: &ScanOptions,
+) -> Result<(), Box<dyn Error>> {
+    let found = Cell::new(0);
+    let hashed = Cell::new(0);
+    let failed = Cell::new(0);
+    // rows, batches, time spent writing and the slowest batch
+    let written = Cell::new((0, 0, Duration::ZERO, Duration::ZERO));
+    let error = Cell::new(None);
+    let control = ScanControl::default();
+
+    // found images and hashes are kept, the next scan continues from there
+    let signal_control = control.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            clear_progress_line();
+            println!("Cancelling the scan");
+            signal_control.cancel();
+        }
+    });
+
+    scanner::scan_folders(connection, options, &control, |status| match status {
+        ScanFolderStatus::Resumed(walked) => {
+            clear_progress_line();
+            println!(
+                "Continuing the previous scan, {} folders are already walked",
+                walked
+            )
+        }
+        ScanFolderStatus::ScanningFolders(folder) => {
+            clear_progress_line();
+            println!("Scanning {}", folder.display())
+        }
+        ScanFolderStatus::ImageFound(_) => found.set(found.get() + 1),
+        ScanFolderStatus::HashCalculated(_) => hashed.set(hashed.get() + 1),
+        ScanFolderStatus::HashFailed(_, _) => failed.set(failed.get() + 1),
+        ScanFolderStatus::BatchWritten(rows, elapsed) => {
+            let (total_rows, batches, total_elapsed, slowest) = written.get();
+            written.set((
+                total_rows + rows,
+                batches + 1,
+                total_elapsed + elapsed,
+                slowest.max(elapsed),
+            ));
+        }
+        // the line is rewritten in place, redirected output gets only the summary
+        ScanFolderStatus::Progress(progress) => {
+            if io::stdout().is_terminal() {
+                print!("\r\x1b[K{}", progress);
+                let _ = io::stdout().flush();
+            }
+        }
+        ScanFolderStatus::Done => {}
+        ScanFolderStatus::Cancelled => {
+            clear_progress_line();
+            println!("Scan cancelled, run it again to continue")
+        }
+        ScanFolderStatus::Failed(err) => error.set(Some(err)),
+    })
+    .await;
+
+    clear_progress_line();
+    println!(
+        "{} new images found, {} hashes calculated, {} images can not be decoded",
+        found.get(),
+        hashed.get(),
+        failed.get()
+    );
+    if failed.get() > 0 {
+        println!("Run `errors` to see why");
+    }
+
+    let (rows, batches, elapsed, slowest) = written.get();
+    if batches > 0 {
+        println!(
+            "{} rows written in {} batches, {:.0} rows/s, the slowest batch took {} ms",
+            rows,
+            batches,
+            rows as f64 / elapsed.as_secs_f64().max(0.001),
+            slowest.as_millis()
+        );
+    }
+
+    match error.take() {
+        Some(err) => Err(format!("Scan stopped, run it again to continue: {}", err).into()),
+        None => Ok(()),
+    }
+}
+
+fn clear_progress_line() {
+    if io::stdout().is_terminal() {
+        print!("\r\x1b[K");
+    }
+}
+
+async fn watch(connection: &mut AcquiredConnection) -> Result<(), Box<dyn Error>> {
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            STOP.store(true, Ordering::Relaxed);
+        }
+    });
+
+    watcher::watch_folders(
+        connection,
+        &ScanOptions::load(),
+        &STOP,
+        |status| match status {
+            WatchStatus::Watching(count) => {
+                println!("Watching {} folders, press Ctrl+C to stop", count)
+            }
+            WatchStatus::ImageChanged(path) => println!("Updated {}", path.display()),
+            WatchStatus::Removed(path) => println!("Removed {}", path.display()),
+            WatchStatus::NewDuplicate(image, duplicate) => println!(
+                "New duplicate: {} is the same as {}",
+                image.display(),
+                duplicate.display()
+            ),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn list_duplicates(connection: &mut AcquiredConnection) -> Result<(), Box<dyn Error>> {
+    for group in connection.get_duplicate_groups().await? {
+        println!("{:016x}", group[0].hash.unwrap_or(0));
+        for image in group.iter() {
+            println!("  {}", image.path.display());
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve(
+    connection: &mut AcquiredConnection,
+    rules: Vec<PolicyRule>,
+    strategy: ResolutionStrategy,
+    apply: bool,
+    plan_id: Option<i64>,
+) -> Result<(), Box<dyn Error>> {
+    let (plan_id, actions) = match plan_id {
+        Some(plan_id) => (plan_id, connection.get_plan_actions(plan_id).await?),
+        None => {
+            let policy = Policy { rules, strategy };
+            plan::build_plan(connection, &policy).await?
+        }
+    };
+
+    // files are changed only when asked explicitly
+    if !apply {
+        println!("Plan #{}", plan_id);
+        print_actions(&actions);
+        println!(
+            "Nothing was changed, run `resolve --plan {} --apply` to apply the plan",
+            plan_id
+        );
+        return Ok(());
+    }
+
+    let actions = plan::apply_plan(connection, plan_id).await?;
+    println!("Applied plan #{}", plan_id);
+    print_actions(&actions);
+
+    Ok(())
+}
+
+async fn report(
+    connection: &mut AcquiredConnection,
+    format: Option<ReportFormat>,
+    output: Option<PathBuf>,
+    thumbnails_dir: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let report = build_report(connection.get_duplicate_groups().await?);
+
+    match output {
+        Some(output) => {
+            let format = format.unwrap_or(ReportFormat::from_path(&output));
+            if format == ReportFormat::Html {
+                write_html_file(&output, &report, thumbnails_dir.as_deref())?;
+            } else {
+                let mut file = fs::File::create(&output)?;
+                write_report(&mut file, &report, format)?;
+            }
+        }
+        None => {
+            let format = format.unwrap_or(ReportFormat::Json);
+            write_report(&mut io::stdout().lock(), &report, format)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn stats(connection: &mut AcquiredConnection) -> Result<(), Box<dyn Error>> {
+    let stats = connection.get_stats().await?;
+    let groups = connection.get_duplicate_groups().await?;
+
+    println!("Folders:          {}", stats.folders);
+    println!("Images:           {}", stats.images);
+    println!("Hashed:           {}", stats.hashed);
+    println!("Reviewed:         {}", stats.protected);
+    println!("Failed:           {}", stats.failed);
+    println!("Duplicate groups: {}", groups.len());
+    println!(
+        "Duplicate images: {}",
+        groups.iter().map(|x| x.len()).sum::<usize>()
+    );
+    if let Some(session) = connection.get_last_scan_session().await? {
+        println!("{}", scanner::describe_session(&session));
+    }
+
+    Ok(())
+}
+
+async fn errors(connection: &mut AcquiredConnection) -> Result<(), Box<dyn Error>> {
+    for image in connection.get_failed_images().await? {
+        let skipped = if image.attempts >= scanner::MAX_HASH_ATTEMPTS {
+            ", skipped until `scan --retry-failed`"
+        } else {
+            ""
+        };
+        println!("{}", image.path.display());
+        println!(
+            "  {} (failed {} times{})",
+            image.error, image.attempts, skipped
+        );
+    }
+
+    Ok(())
+}
+
+fn libraries() -> Result<(), Box<dyn Error>> {
+    for library in config::list_libraries() {
+        println!("{}", library);
+    }
+
+    Ok(())
+}
+
+fn print_actions(actions: &[PlanActionWrapper]) {
+    for action in actions {
+        println!(
+            "[{}] {} {} (keep {}: {})",
+            action.status,
+            action.strategy,
+            action.remove_path.display(),
+            action.keep_path.display(),
+            action.reason
+        );
+
+        if let Some(error) = &action.error {
+            println!("    {}", error);
+        }
+    }
+}
+
+fn parse_rule(value: &str) -> Result<PolicyRule, String> {
+    PolicyRule::parse(value).ok_or(format!("unknown rule {}", value))
+}
+
+fn parse_library(value: &str) -> Result<String, String> {
+    if config::is_valid_library_name(value) {
+        Ok(value.to_string())
+    } else {
+        Err("only letters, digits, '-' and '_' are allowed".to_string())
+    }
+}
+
+fn parse_format(value: &str) -> Result<ReportFormat, String> {
+    ReportFormat::from_name(value).ok_or(format!("unknown format {}", value))
+}
+
+fn parse_strategy(value: &str) -> Result<ResolutionStrategy, String> {
+    ResolutionStrategy::from_name(value).ok_or(format!("unknown strategy {}", value))
+}
@@ -0,0 +1,252 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const APP_DIR: &str = "deduplicator";
+pub const DATABASE_ENV: &str = "DEDUPLICATOR_DATABASE";
+pub const LIBRARY_ENV: &str = "DEDUPLICATOR_LIBRARY";
+// library stored in the main database file
+pub const DEFAULT_LIBRARY: &str = "default";
+const DATABASE_FILE: &str = "database.sqlite";
+const LIBRARIES_DIR: &str = "libraries";
+const THUMBNAILS_DIR: &str = "thumbnails";
+const LIBRARY_EXTENSION: &str = "sqlite";
+const CONFIG_FILE: &str = "config";
+// service folders of VCS, file managers and NAS, skipped in every scanned folder
+pub const DEFAULT_EXCLUDES: [&str; 5] = [".git", "@eaDir", ".thumbnails", ".Trash-*", "lost+found"];
+
+// settings from `$XDG_CONFIG_HOME/deduplicator/config`, one `key = value` per line
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    pub database: Option<PathBuf>,
+    // gitignore-style patterns added to `DEFAULT_EXCLUDES`, one `exclude = <pattern>` line each
+    pub exclude: Vec<String>,
+    pub follow_symlinks: Option<bool>,
+    pub same_filesystem: Option<bool>,
+    pub dedupe_hardlinks: Option<bool>,
+    pub scan_archives: Option<bool>,
+    // number of images hashed at once, all cores by default
+    pub hash_threads: Option<usize>,
+    // memory for images decoded at once, in megabytes
+    pub hash_memory_mb: Option<u64>,
+}
+
+impl Config {
+    // missing or unreadable config file gives default settings
+    pub fn load() -> Self {
+        config_home()
+            .map(|x| x.join(APP_DIR).join(CONFIG_FILE))
+            .and_then(|x| fs::read_to_string(x).ok())
+            .map(|x| Self::parse(&x))
+            .unwrap_or_default()
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut result = Self::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    log::warn!("Wrong config line: {}", line);
+                    continue;
+                }
+            };
+
+            match key {
+                "database" => result.database = Some(expand_home(value)),
+                "exclude" => result.exclude.push(value.to_string()),
+                "follow_symlinks" => result.follow_symlinks = parse_bool(key, value),
+                "same_filesystem" => result.same_filesystem = parse_bool(key, value),
+                "dedupe_hardlinks" => result.dedupe_hardlinks = parse_bool(key, value),
+                "scan_archives" => result.scan_archives = parse_bool(key, value),
+                "hash_threads" => result.hash_threads = parse_number(key, value),
+                "hash_memory_mb" => result.hash_memory_mb = parse_number(key, value),
+                _ => log::warn!("Unknown config key: {}", key),
+            }
+        }
+
+        result
+    }
+}
+
+// command line flag wins over environment variable, which wins over config file
+pub fn database_path(flag: Option<&Path>) -> PathBuf {
+    if let Some(path) = flag {
+        return path.to_path_buf();
+    }
+
+    if let Some(path) = env::var_os(DATABASE_ENV).filter(|x| !x.is_empty()) {
+        return PathBuf::from(path);
+    }
+
+    if let Some(path) = Config::load().database {
+        return path;
+    }
+
+    default_database_path()
+}
+
+// patterns applied to every scanned folder before its own ones
+pub fn global_excludes() -> Vec<String> {
+    DEFAULT_EXCLUDES
+        .iter()
+        .map(|x| x.to_string())
+        .chain(Config::load().exclude)
+        .collect()
+}
+
+// `$XDG_DATA_HOME/deduplicator/database.sqlite`, current folder if home is unknown
+pub fn default_database_path() -> PathBuf {
+    match data_home() {
+        Some(data_home) => data_home.join(APP_DIR).join(DATABASE_FILE),
+        None => PathBuf::from(DATABASE_FILE),
+    }
+}
+
+// library chosen by environment variable, default one otherwise
+pub fn current_library() -> String {
+    env::var(LIBRARY_ENV)
+        .ok()
+        .filter(|x| is_valid_library_name(x))
+        .unwrap_or(DEFAULT_LIBRARY.to_string())
+}
+
+// every library has its own database file, so images of different libraries are never compared
+pub fn library_database_path(library: &str) -> PathBuf {
+    if library == DEFAULT_LIBRARY {
+        return database_path(None);
+    }
+
+    libraries_dir().join(format!("{}.{}", library, LIBRARY_EXTENSION))
+}
+
+// default library first, then others sorted by name
+pub fn list_libraries() -> Vec<String> {
+    let mut result: Vec<String> = fs::read_dir(libraries_dir())
+        .map(|x| {
+            x.filter_map(|x| x.ok())
+                .map(|x| x.path())
+                .filter(|x| x.extension() == Some(OsStr::new(LIBRARY_EXTENSION)))
+                .filter_map(|x| x.file_stem().map(|x| x.to_string_lossy().to_string()))
+                .filter(|x| is_valid_library_name(x) && x != DEFAULT_LIBRARY)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    result.sort();
+    result.insert(0, DEFAULT_LIBRARY.to_string());
+
+    result
+}
+
+pub fn is_valid_library_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+}
+
+fn libraries_dir() -> PathBuf {
+    match data_home() {
+        Some(data_home) => data_home.join(APP_DIR).join(LIBRARIES_DIR),
+        None => PathBuf::from(LIBRARIES_DIR),
+    }
+}
+
+// `$XDG_CACHE_HOME/thumbnails` of the freedesktop specification, shared with other
+// applications and can be deleted any time
+pub fn thumbnails_dir() -> PathBuf {
+    match cache_home() {
+        Some(cache_home) => cache_home.join(THUMBNAILS_DIR),
+        None => PathBuf::from(THUMBNAILS_DIR),
+    }
+}
+
+pub fn data_home() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn config_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn cache_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+// relative values of xdg variables are ignored as the specification requires
+fn xdg_dir(variable: &str, home_fallback: &str) -> Option<PathBuf> {
+    env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|x| x.is_absolute())
+        .or_else(|| home_dir().map(|x| x.join(home_fallback)))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+fn parse_bool(key: &str, value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => {
+            log::warn!("Wrong value of {}: {}", key, value);
+            None
+        }
+    }
+}
+
+// positive numbers only, zero makes no sense for any numeric setting
+fn parse_number<T: std::str::FromStr + PartialOrd + Default>(key: &str, value: &str) -> Option<T> {
+    match value.parse::<T>() {
+        Ok(number) if number > T::default() => Some(number),
+        _ => {
+            log::warn!("Wrong value of {}: {}", key, value);
+            None
+        }
+    }
+}
+
+fn expand_home(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse(
+        "# comment\n\n database = /data/photos.sqlite \nexclude = *.tmp\nexclude = /raw/\n",
+    );
+    assert_eq!(Some(PathBuf::from("/data/photos.sqlite")), config.database);
+    assert_eq!(vec!["*.tmp", "/raw/"], config.exclude);
+
+    let config = Config::parse("follow_symlinks = yes\nsame_filesystem = maybe\n");
+    assert_eq!(Some(true), config.follow_symlinks);
+    assert_eq!(None, config.same_filesystem);
+
+    let config = Config::parse("hash_threads = 4\nhash_memory_mb = 0\n");
+    assert_eq!(Some(4), config.hash_threads);
+    assert_eq!(None, config.hash_memory_mb);
+
+    assert_eq!(Config::default(), Config::parse("wrong line\n"));
+}
+
+#[test]
+fn test_library_name() {
+    assert!(is_valid_library_name("product-shots_2023"));
+    assert!(!is_valid_library_name(""));
+    assert!(!is_valid_library_name("../archive"));
+}
@@ -1,6 +0,0 @@
-mod database;
-
-pub use database::AcquiredConnection;
-pub use database::Database;
-pub use database::FolderWrapper;
-pub use database::ImageWrapper;
@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// file with patterns for its folder and all subfolders, like `.gitignore`
+pub const IGNORE_FILE: &str = ".dedupignore";
+
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    // patterns with `/` are matched against the path relative to the base, others against the name
+    anchored: bool,
+}
+
+// gitignore-style patterns relative to the base folder, the last matching pattern wins
+pub struct IgnoreRules {
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreRules {
+    pub fn new<'a>(base: &Path, lines: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            patterns: lines.into_iter().filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    // rules of `.dedupignore` inside the folder, `None` if there is no such file
+    pub fn load(folder: &Path) -> Option<Self> {
+        let path = folder.join(IGNORE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => Some(Self::new(folder, text.lines())),
+            Err(err) => {
+                if path.exists() {
+                    log::warn!("Can not read {}: {}", path.display(), err);
+                }
+                None
+            }
+        }
+    }
+
+    // `Some(false)` when the path is explicitly included back with `!`
+    pub fn check(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative_path = path.strip_prefix(&self.base).ok()?;
+        let relative_path: Vec<char> = relative_path
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+        let name: Vec<char> = path.file_name()?.to_string_lossy().chars().collect();
+
+        self.patterns
+            .iter()
+            .rev()
+            .filter(|x| is_dir || !x.dir_only)
+            .find(|x| {
+                if x.anchored {
+                    glob_match(&x.glob, &relative_path)
+                } else {
+                    glob_match(&x.glob, &name)
+                }
+            })
+            .map(|x| !x.negated)
+    }
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+        if negated {
+            line = &line[1..];
+        }
+
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+}
+
+// rules of outer folders go first, so patterns of nested `.dedupignore` files win
+pub fn is_ignored(rules: &[Arc<IgnoreRules>], path: &Path, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find_map(|x| x.check(path, is_dir))
+        .unwrap_or(false)
+}
+
+// `*` and `?` do not match `/`, `**` matches any number of folders
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|x| glob_match(rest, &text[x..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for x in 0..=text.len() {
+                if glob_match(rest, &text[x..]) {
+                    return true;
+                }
+                if x < text.len() && text[x] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match(&pattern[1..], &text[1..]),
+        Some('[') => match (match_class(pattern, text.first()), text.first()) {
+            (Some((matched, length)), Some(_)) => {
+                matched && glob_match(&pattern[length..], &text[1..])
+            }
+            (Some(_), None) => false,
+            // not closed class is a plain character
+            (None, _) => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(x) => text.first() == Some(x) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+// `[abc]`, `[a-z]` or `[!abc]`, returns whether the character matched and length of the class
+fn match_class(pattern: &[char], character: Option<&char>) -> Option<(bool, usize)> {
+    let mut position = 1;
+    let negated = matches!(pattern.get(position), Some('!') | Some('^'));
+    if negated {
+        position += 1;
+    }
+
+    let mut matched = false;
+    let start = position;
+    while let Some(x) = pattern.get(position) {
+        if *x == ']' && position > start {
+            let matched = matched != negated && matches!(character, Some(x) if *x != '/');
+            return Some((matched, position + 1));
+        }
+
+        if pattern.get(position + 1) == Some(&'-')
+            && !matches!(pattern.get(position + 2), None | Some(']'))
+        {
+            let end = pattern[position + 2];
+            matched |= matches!(character, Some(c) if *x <= *c && *c <= end);
+            position += 3;
+        } else {
+            matched |= character == Some(x);
+            position += 1;
+        }
+    }
+
+    None
+}
+
+#[test]
+fn test_glob_match() {
+    let glob_match = |pattern: &str, text: &str| {
+        glob_match(
+            &pattern.chars().collect::<Vec<_>>(),
+            &text.chars().collect::<Vec<_>>(),
+        )
+    };
+
+    assert!(glob_match("*.jpg", "photo.jpg"));
+    assert!(!glob_match("*.jpg", "folder/photo.jpg"));
+    assert!(glob_match("**/*.jpg", "photo.jpg"));
+    assert!(glob_match("**/*.jpg", "a/b/photo.jpg"));
+    assert!(glob_match("a/**/c", "a/c"));
+    assert!(glob_match("a/**/c", "a/b/b/c"));
+    assert!(glob_match("IMG_????.png", "IMG_0001.png"));
+    assert!(glob_match("[a-c]x[!y]", "bxz"));
+    assert!(!glob_match("[a-c]x[!y]", "bxy"));
+    assert!(glob_match("\\*", "*"));
+    assert!(glob_match("[", "["));
+}
+
+#[test]
+fn test_ignore_rules() {
+    let base = Path::new("/photos");
+    let rules = IgnoreRules::new(
+        base,
+        [
+            "# comment",
+            "@eaDir",
+            "/raw/",
+            "*.png",
+            "!keep.png",
+            "cache/**",
+        ],
+    );
+
+    assert_eq!(Some(true), rules.check(&base.join("a/@eaDir"), true));
+    assert_eq!(Some(true), rules.check(&base.join("raw"), true));
+    assert_eq!(None, rules.check(&base.join("raw"), false));
+    assert_eq!(None, rules.check(&base.join("a/raw"), true));
+    assert_eq!(Some(true), rules.check(&base.join("a/b.png"), false));
+    assert_eq!(Some(false), rules.check(&base.join("a/keep.png"), false));
+    assert_eq!(Some(true), rules.check(&base.join("cache/x.jpg"), false));
+    assert_eq!(None, rules.check(Path::new("/other/b.png"), false));
+
+    let nested = IgnoreRules::new(&base.join("a"), ["!*.png"]);
+    assert!(is_ignored(
+        &[Arc::new(IgnoreRules::new(base, ["*.png"]))],
+        &base.join("a/b.png"),
+        false
+    ));
+    assert!(!is_ignored(
+        &[
+            Arc::new(IgnoreRules::new(base, ["*.png"])),
+            Arc::new(nested)
+        ],
+        &base.join("a/b.png"),
+        false
+    ));
+}
@@ -1,34 +1,47 @@
-use std::fs;
-
-pub fn find_file_recursive(path: String, extensions: &Vec<String>) -> Vec<String> {
-    let dir_data = fs::read_dir(path);
-
-    let mut result = Vec::new();
-
-    if let Ok(dir_data) = dir_data {
-        let dir_data: Vec<fs::DirEntry> = dir_data.filter_map(|x| x.ok()).collect();
-
-        for folder_value in dir_data {
-            let path = folder_value.path();
-            let path_str = path.to_str();
-            if let Some(path_str) = path_str {
-                let path_str = path_str.to_string();
-                if path.is_file() && is_correct_extension(&path_str, extensions) {
-                    result.push(path_str.to_string());
-                } else if path.is_dir() {
-                    let mut files = find_file_recursive(path_str, extensions);
-                    result.append(&mut files);
-                }
-            }
+pub mod ignore;
+mod walker;
+
+pub use walker::Walker;
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Clone)]
+pub struct WalkOptions {
+    pub extensions: Vec<String>,
+    // gitignore-style patterns relative to the walked folder
+    pub excludes: Vec<String>,
+    // symlinks are skipped otherwise
+    pub follow_symlinks: bool,
+    // do not descend into folders mounted from other devices
+    pub same_filesystem: bool,
+    // report only the first found name of a hardlinked or symlinked file
+    pub dedupe_hardlinks: bool,
+}
+
+impl WalkOptions {
+    pub fn new(extensions: Vec<String>) -> Self {
+        Self {
+            extensions,
+            excludes: Vec::new(),
+            follow_symlinks: false,
+            same_filesystem: false,
+            dedupe_hardlinks: true,
         }
     }
+}
 
-    result
+// collects all files of the folder, use `Walker` to get them while the walk goes on
+pub fn find_files(path: PathBuf, options: WalkOptions) -> Vec<PathBuf> {
+    Walker::default().walk(path, options).iter().collect()
 }
 
-fn is_correct_extension(path: &String, extensions: &Vec<String>) -> bool {
+// names are compared as bytes, so they do not have to be valid UTF-8
+pub(crate) fn is_correct_extension(path: &Path, extensions: &[String]) -> bool {
+    let path = path.as_os_str().as_bytes();
     for extension in extensions.iter() {
-        if path.ends_with(extension) {
+        if path.ends_with(extension.as_bytes()) {
             return true;
         }
     }
@@ -36,10 +49,99 @@ fn is_correct_extension(path: &String, extensions: &Vec<String>) -> bool {
     false
 }
 
+// sizes for people, shared by scan progress and reports
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
 #[test]
 fn test_find_folders() {
-    let extensions = vec![".jpg".to_string()];
-    let result = find_file_recursive("tests/test_folders".to_string(), &extensions);
+    let mut options = WalkOptions::new(vec![".jpg".to_string()]);
+    let result = find_files(PathBuf::from("tests/test_folders"), options.clone());
 
     assert_eq!(5, result.len());
+
+    options.excludes = vec!["f2".to_string(), "/1.jpg".to_string()];
+    let result = find_files(PathBuf::from("tests/test_folders"), options.clone());
+
+    assert_eq!(2, result.len());
+}
+
+#[test]
+fn test_ignore_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    std::fs::create_dir_all(root.join("a/@eaDir")).unwrap();
+    for file in ["1.jpg", "a/2.jpg", "a/3.jpg", "a/@eaDir/1.jpg"] {
+        std::fs::write(root.join(file), "").unwrap();
+    }
+    std::fs::write(root.join(ignore::IGNORE_FILE), "@eaDir\n*.jpg\n").unwrap();
+    std::fs::write(root.join("a").join(ignore::IGNORE_FILE), "!2.jpg\n").unwrap();
+
+    let options = WalkOptions::new(vec![".jpg".to_string()]);
+    let result = find_files(root.clone(), options);
+
+    assert_eq!(vec![root.join("a/2.jpg")], result);
+}
+
+#[test]
+fn test_links() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    std::fs::create_dir_all(root.join("a")).unwrap();
+    std::fs::write(root.join("a/1.jpg"), "").unwrap();
+    std::fs::hard_link(root.join("a/1.jpg"), root.join("a/2.jpg")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("a/loop")).unwrap();
+    std::os::unix::fs::symlink(root.join("a/1.jpg"), root.join("3.jpg")).unwrap();
+
+    let mut options = WalkOptions::new(vec![".jpg".to_string()]);
+    let root_path = root.clone();
+
+    assert_eq!(1, find_files(root_path.clone(), options.clone()).len());
+
+    options.dedupe_hardlinks = false;
+    assert_eq!(2, find_files(root_path.clone(), options.clone()).len());
+
+    // the loop leads to the already walked root
+    options.follow_symlinks = true;
+    assert_eq!(3, find_files(root_path.clone(), options.clone()).len());
+
+    options.dedupe_hardlinks = true;
+    assert_eq!(1, find_files(root_path, options).len());
+}
+
+#[test]
+fn test_not_utf8_names() {
+    use std::ffi::OsStr;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let folder = root.join(OsStr::from_bytes(b"\xff\xfe"));
+    let file = folder.join(OsStr::from_bytes(b"caf\xe9.jpg"));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(&file, "").unwrap();
+
+    let result = find_files(root.clone(), WalkOptions::new(vec![".jpg".to_string()]));
+
+    assert_eq!(vec![file], result);
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!("512 B", format_bytes(512));
+    assert_eq!("1.5 KB", format_bytes(1536));
+    assert_eq!("2.0 MB", format_bytes(2 * 1024 * 1024));
+    assert_eq!("3.0 TB", format_bytes(3 * 1024 * 1024 * 1024 * 1024));
 }
@@ -0,0 +1,269 @@
+use super::ignore::is_ignored;
+use super::ignore::IgnoreRules;
+use super::is_correct_extension;
+use super::WalkOptions;
+use crate::scanner::ScanControl;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+
+// folders are read in parallel, it helps most on network storage
+const WALK_THREADS: usize = 8;
+
+// walks folders on several threads and sends found files as soon as they are found;
+// walked folders and found files are remembered by (device, inode), so several walks
+// with the same walker never return a file twice and symlink loops are cut
+#[derive(Clone)]
+pub struct Walker {
+    threads: usize,
+    control: ScanControl,
+    visited_folders: Arc<Mutex<HashSet<(u64, u64)>>>,
+    found_files: Arc<Mutex<HashSet<(u64, u64)>>>,
+}
+
+struct Folder {
+    path: PathBuf,
+    // rules of the walked folder and `.dedupignore` files of all parents
+    rules: Vec<Arc<IgnoreRules>>,
+}
+
+// folders waiting to be read and number of folders being read right now
+struct Queue {
+    folders: VecDeque<Folder>,
+    active: usize,
+}
+
+struct Walk {
+    options: WalkOptions,
+    root_device: u64,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    // set when nobody waits for files anymore
+    stopped: AtomicBool,
+    control: ScanControl,
+    visited_folders: Arc<Mutex<HashSet<(u64, u64)>>>,
+    found_files: Arc<Mutex<HashSet<(u64, u64)>>>,
+}
+
+impl Default for Walker {
+    fn default() -> Self {
+        Self::new(WALK_THREADS)
+    }
+}
+
+impl Walker {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            control: ScanControl::default(),
+            visited_folders: Arc::new(Mutex::new(HashSet::new())),
+            found_files: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // walks are paused and cancelled with the scan, a cancelled walk closes the receiver
+    // without reading the remaining folders
+    pub fn with_control(mut self, control: ScanControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    // files come in no particular order, the receiver is closed when the walk is over;
+    // dropping the receiver stops the walk
+    pub fn walk(&self, path: PathBuf, options: WalkOptions) -> mpsc::Receiver<PathBuf> {
+        let (sender, receiver) = mpsc::channel();
+
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                log::warn!("Can not read {}: {}", path.display(), err);
+                return receiver;
+            }
+        };
+
+        if !self
+            .visited_folders
+            .lock()
+            .unwrap()
+            .insert((metadata.dev(), metadata.ino()))
+        {
+            return receiver;
+        }
+
+        let root = path;
+        let rules = vec![Arc::new(IgnoreRules::new(
+            &root,
+            options.excludes.iter().map(|x| x.as_str()),
+        ))];
+
+        let walk = Arc::new(Walk {
+            options,
+            root_device: metadata.dev(),
+            queue: Mutex::new(Queue {
+                folders: VecDeque::from([Folder { path: root, rules }]),
+                active: 0,
+            }),
+            changed: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            control: self.control.clone(),
+            visited_folders: self.visited_folders.clone(),
+            found_files: self.found_files.clone(),
+        });
+
+        for _ in 0..self.threads {
+            let walk = walk.clone();
+            let sender = sender.clone();
+            thread::spawn(move || walk.run(sender));
+        }
+
+        receiver
+    }
+}
+
+impl Walk {
+    fn run(&self, sender: mpsc::Sender<PathBuf>) {
+        while let Some(folder) = self.next_folder() {
+            if !self.stopped.load(Ordering::Relaxed) && self.control.wait() {
+                self.read_folder(folder, &sender);
+            }
+
+            let mut queue = self.queue.lock().unwrap();
+            queue.active -= 1;
+            if queue.active == 0 && queue.folders.is_empty() {
+                self.changed.notify_all();
+            }
+        }
+    }
+
+    // `None` when there are no folders left and nobody can add new ones
+    fn next_folder(&self) -> Option<Folder> {
+        let mut queue = self.queue.lock().unwrap();
+
+        loop {
+            // the latest folders first, so the queue stays as short as the tree is deep
+            if let Some(folder) = queue.folders.pop_back() {
+                queue.active += 1;
+                return Some(folder);
+            }
+
+            if queue.active == 0 {
+                return None;
+            }
+
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    fn read_folder(&self, folder: Folder, sender: &mpsc::Sender<PathBuf>) {
+        let dir_data = match fs::read_dir(&folder.path) {
+            Ok(dir_data) => dir_data,
+            Err(err) => {
+                log::warn!("Can not read {}: {}", folder.path.display(), err);
+                return;
+            }
+        };
+
+        let mut rules = folder.rules;
+        rules.extend(IgnoreRules::load(&folder.path).map(Arc::new));
+
+        let mut subfolders = Vec::new();
+
+        for folder_value in dir_data.filter_map(|x| x.ok()) {
+            let path = folder_value.path();
+
+            let is_symlink = folder_value
+                .file_type()
+                .map(|x| x.is_symlink())
+                .unwrap_or(false);
+            if is_symlink && !self.options.follow_symlinks {
+                continue;
+            }
+
+            // metadata of symlink target, broken links are skipped
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let key = (metadata.dev(), metadata.ino());
+
+            if is_ignored(&rules, &path, metadata.is_dir()) {
+                continue;
+            }
+
+            if metadata.is_file() && is_correct_extension(&path, &self.options.extensions) {
+                if self.options.dedupe_hardlinks && !self.found_files.lock().unwrap().insert(key) {
+                    continue;
+                }
+                if sender.send(path).is_err() {
+                    self.stopped.store(true, Ordering::Relaxed);
+                    return;
+                }
+            } else if metadata.is_dir() {
+                if self.options.same_filesystem && metadata.dev() != self.root_device {
+                    continue;
+                }
+                if !self.visited_folders.lock().unwrap().insert(key) {
+                    continue;
+                }
+                subfolders.push(Folder {
+                    path,
+                    rules: rules.clone(),
+                });
+            }
+        }
+
+        if !subfolders.is_empty() {
+            let mut queue = self.queue.lock().unwrap();
+            queue.folders.extend(subfolders);
+            self.changed.notify_all();
+        }
+    }
+}
+
+#[test]
+fn test_walker() {
+    let options = || WalkOptions::new(vec![".jpg".to_string()]);
+    let walker = Walker::new(3);
+
+    let mut result: Vec<String> = walker
+        .walk(PathBuf::from("tests/test_folders"), options())
+        .iter()
+        .map(|x| x.to_string_lossy().to_string())
+        .collect();
+    result.sort();
+    assert_eq!(
+        vec![
+            "tests/test_folders/1.jpg",
+            "tests/test_folders/2.jpg",
+            "tests/test_folders/f1/3.jpg",
+            "tests/test_folders/f2/4.jpg",
+            "tests/test_folders/f2/f3/5.jpg",
+        ],
+        result
+    );
+
+    // already walked by this walker
+    assert_eq!(
+        0,
+        walker
+            .walk(PathBuf::from("tests/test_folders/f2"), options())
+            .iter()
+            .count()
+    );
+    assert_eq!(
+        0,
+        walker
+            .walk(PathBuf::from("tests/not_existing"), options())
+            .iter()
+            .count()
+    );
+}
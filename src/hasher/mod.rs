@@ -0,0 +1,125 @@
+pub mod pool;
+
+use crate::archive::load_image_scaled;
+use crate::archive::open_image_scaled;
+use image;
+use std::path::Path;
+
+// stored hashes of other versions are calculated again by the next scan.
+// 2: jpeg images are decoded at reduced size
+// 3: pixels of all images are averaged, so jpeg images match copies in other formats
+pub const HASH_VERSION: i64 = 3;
+
+// the hash is built from 8x8 pixels, so images do not have to be decoded at full size
+const HASH_SIZE: u16 = 8;
+
+// 64 bit average hash of the image, `None` if the file can not be decoded.
+// images inside archives are read by their virtual paths
+pub fn get_image_hash(path: &Path) -> Option<i64> {
+    try_image_hash(path).ok()
+}
+
+// hash of the image or the reason why it can not be decoded
+pub fn try_image_hash(path: &Path) -> Result<i64, String> {
+    open_image_scaled(path, HASH_SIZE)
+        .map(calc_hash)
+        .map_err(|err| err.to_string())
+}
+
+// hash of the encoded image read into memory
+pub fn get_image_data_hash(data: &[u8]) -> Result<i64, String> {
+    load_image_scaled(data, HASH_SIZE)
+        .map(calc_hash)
+        .map_err(|err| err.to_string())
+}
+
+// every pixel is averaged into its cell of the 8x8 grid, so the hash does not depend on
+// the size the image was decoded at
+pub fn calc_hash(img: image::DynamicImage) -> i64 {
+    let img = img.to_luma8();
+    let columns = cell_weights(img.width());
+    let rows = cell_weights(img.height());
+
+    let mut cells = [0u64; 64];
+    for (x, y, pixel) in img.enumerate_pixels() {
+        for &(row, row_weight) in rows[y as usize].iter() {
+            for &(column, column_weight) in columns[x as usize].iter() {
+                cells[row * 8 + column] += pixel.0[0] as u64 * row_weight * column_weight;
+            }
+        }
+    }
+
+    // all cells cover the same area, so their sums are compared with the mean directly
+    let total: u64 = cells.iter().sum();
+
+    let mut hash: i64 = 0;
+    let mut marker: i64 = 1;
+    for cell in cells {
+        if cell * 64 > total {
+            hash |= marker;
+        }
+        marker <<= 1;
+    }
+
+    hash
+}
+
+// cells of the grid covered by every pixel of the line with the covered part as weight,
+// a pixel is 8 units long and a cell `length` units
+fn cell_weights(length: u32) -> Vec<Vec<(usize, u64)>> {
+    let length = length as u64;
+    (0..length)
+        .map(|pixel| {
+            let start = pixel * 8;
+            let end = start + 8;
+            (start / length..=(end - 1) / length)
+                .map(|cell| {
+                    let covered = end.min((cell + 1) * length) - start.max(cell * length);
+                    (cell as usize, covered)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// number of different bits, 0 means the same image
+pub fn hash_distance(left: i64, right: i64) -> u32 {
+    (left ^ right).count_ones()
+}
+
+#[test]
+fn test_scaled_jpeg_hash() {
+    let gradient = image::RgbImage::from_fn(640, 480, |x, y| {
+        image::Rgb([(x * 255 / 640) as u8, (y * 255 / 480) as u8, 128])
+    });
+    let mut data = Vec::new();
+    image::DynamicImage::ImageRgb8(gradient)
+        .write_to(
+            &mut std::io::Cursor::new(&mut data),
+            image::ImageOutputFormat::Jpeg(90),
+        )
+        .unwrap();
+
+    let scaled = load_image_scaled(&data, HASH_SIZE).unwrap();
+    assert_eq!((80, 60), (scaled.width(), scaled.height()));
+
+    // the same as the hash of the fully decoded image
+    let full = calc_hash(image::load_from_memory(&data).unwrap());
+    assert_eq!(full, get_image_data_hash(&data).unwrap());
+}
+
+#[test]
+fn test_cell_weights() {
+    // 12 pixels, every cell takes one and a half of them
+    let weights = cell_weights(12);
+    assert_eq!(vec![(0, 8)], weights[0]);
+    assert_eq!(vec![(0, 4), (1, 4)], weights[1]);
+    assert_eq!(vec![(7, 8)], weights[11]);
+
+    // smaller images are stretched over the grid
+    assert_eq!(vec![(0, 2), (1, 2), (2, 2), (3, 2)], cell_weights(2)[0]);
+
+    let image = image::GrayImage::from_fn(16, 16, |x, _| image::Luma([(x * 16) as u8]));
+    let hash = calc_hash(image::DynamicImage::ImageLuma8(image));
+    assert_eq!(0xf0f0f0f0f0f0f0f0u64 as i64, hash);
+}
@@ -0,0 +1,361 @@
+use super::get_image_data_hash;
+use crate::archive;
+use crate::index::ImageWrapper;
+use crate::scanner::ScanControl;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::io::Cursor;
+use std::io::Seek;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+
+// decoded images of the default budget, 1 GiB
+pub const DEFAULT_MEMORY_LIMIT: u64 = 1024 * 1024 * 1024;
+
+// hashes images on several threads, every thread takes the next image from a shared
+// queue when it is free, so a few huge images do not leave other threads idle
+pub struct HashPool {
+    threads: usize,
+    memory: Arc<MemoryBudget>,
+    control: ScanControl,
+}
+
+// bytes of decoded images which may be held at once
+struct MemoryBudget {
+    limit: u64,
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+impl Default for HashPool {
+    fn default() -> Self {
+        Self::new(default_threads(), DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl HashPool {
+    pub fn new(threads: usize, memory_limit: u64) -> Self {
+        Self {
+            threads: threads.max(1),
+            memory: Arc::new(MemoryBudget {
+                limit: memory_limit,
+                in_use: Mutex::new(0),
+                released: Condvar::new(),
+            }),
+            control: ScanControl::default(),
+        }
+    }
+
+    // workers wait before the next image while the scan is paused and stop when it is
+    // cancelled, images left in the queue are not sent
+    pub fn with_control(mut self, control: ScanControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.is_cancelled()
+    }
+
+    // images come back with their hashes or failure reasons in no particular order, every
+    // image exactly once; the receiver is closed when all of them are sent or the scan is cancelled.
+    // images inside archives are decoded from memory, `hash` is used for files on disk
+    pub fn start(
+        &self,
+        images: Vec<ImageWrapper>,
+        hash: fn(&Path) -> Result<i64, String>,
+    ) -> mpsc::Receiver<(ImageWrapper, Result<i64, String>)> {
+        let (sender, receiver) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(jobs(images)));
+
+        for _ in 0..self.threads {
+            let queue = queue.clone();
+            let worker = Worker {
+                memory: self.memory.clone(),
+                control: self.control.clone(),
+                sender: sender.clone(),
+                hash,
+            };
+
+            thread::spawn(move || loop {
+                if !worker.control.wait() {
+                    return;
+                }
+                let job = queue.lock().unwrap().pop_front();
+                let is_running = match job {
+                    Some(Job::Image(image)) => worker.hash_image(image),
+                    Some(Job::Archive(archive, images)) => worker.hash_archive(&archive, images),
+                    None => return,
+                };
+                if !is_running {
+                    return;
+                }
+            });
+        }
+
+        receiver
+    }
+}
+
+enum Job {
+    Image(ImageWrapper),
+    // images inside one archive, which is read once for all of them
+    Archive(PathBuf, Vec<ImageWrapper>),
+}
+
+// archives go first, a big one should not be left for the end of the scan
+fn jobs(images: Vec<ImageWrapper>) -> VecDeque<Job> {
+    let mut archives: Vec<(PathBuf, Vec<ImageWrapper>)> = Vec::new();
+    let mut result = VecDeque::new();
+
+    for image in images {
+        let archive = match archive::split_virtual_path(&image.path) {
+            Some((archive, _)) => archive.to_path_buf(),
+            None => {
+                result.push_back(Job::Image(image));
+                continue;
+            }
+        };
+        match archives.iter_mut().find(|x| x.0 == archive) {
+            Some((_, images)) => images.push(image),
+            None => archives.push((archive, vec![image])),
+        }
+    }
+
+    for (archive, images) in archives.into_iter().rev() {
+        result.push_front(Job::Archive(archive, images));
+    }
+
+    result
+}
+
+struct Worker {
+    memory: Arc<MemoryBudget>,
+    control: ScanControl,
+    sender: mpsc::Sender<(ImageWrapper, Result<i64, String>)>,
+    hash: fn(&Path) -> Result<i64, String>,
+}
+
+impl Worker {
+    // `false` when nobody waits for hashes anymore
+    fn hash_image(&self, image: ImageWrapper) -> bool {
+        let cost = image::io::Reader::open(&image.path)
+            .map(decoded_size)
+            .unwrap_or(0);
+        let path = image.path.clone();
+        let hash = self.hash;
+
+        self.send(image, cost, || hash(&path))
+    }
+
+    // the archive is read once and its images are hashed while it is read,
+    // images which can not be read from it are sent with the reason
+    fn hash_archive(&self, archive: &Path, images: Vec<ImageWrapper>) -> bool {
+        let paths: HashSet<PathBuf> = images.iter().map(|x| x.path.clone()).collect();
+        let mut left: HashMap<PathBuf, ImageWrapper> =
+            images.into_iter().map(|x| (x.path.clone(), x)).collect();
+        let mut is_running = true;
+
+        let result = archive::read_images(archive, &paths, |path, data| {
+            let image = match left.remove(&path) {
+                Some(image) => image,
+                None => return true,
+            };
+            is_running = self.control.wait() && {
+                let cost = image::io::Reader::new(Cursor::new(&data))
+                    .with_guessed_format()
+                    .map(decoded_size)
+                    .unwrap_or(0);
+                self.send(image, cost, || get_image_data_hash(&data))
+            };
+            is_running
+        });
+
+        if !is_running {
+            return false;
+        }
+
+        let reason = match result {
+            Ok(()) => "not found in the archive".to_string(),
+            Err(err) => format!("can not read the archive: {}", err),
+        };
+        left.into_values()
+            .all(|image| self.sender.send((image, Err(reason.clone()))).is_ok())
+    }
+
+    fn send(
+        &self,
+        mut image: ImageWrapper,
+        cost: u64,
+        hash: impl FnOnce() -> Result<i64, String> + panic::UnwindSafe,
+    ) -> bool {
+        self.memory.acquire(cost);
+        // decoders may panic on broken files, such image is sent without hash
+        let result =
+            panic::catch_unwind(hash).unwrap_or_else(|_| Err("decoder panicked".to_string()));
+        self.memory.release(cost);
+        image.hash = result.as_ref().ok().copied();
+
+        self.sender.send((image, result)).is_ok()
+    }
+}
+
+impl MemoryBudget {
+    // an image bigger than the whole budget waits until nothing else is decoded
+    fn acquire(&self, cost: u64) {
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use > 0 && *in_use + cost > self.limit {
+            in_use = self.released.wait(in_use).unwrap();
+        }
+        *in_use += cost;
+    }
+
+    fn release(&self, cost: u64) {
+        *self.in_use.lock().unwrap() -= cost;
+        self.released.notify_all();
+    }
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1)
+}
+
+// dimensions are read from the header without decoding,
+// jpeg images are decoded at 1/8 of their size
+fn decoded_size<R: BufRead + Seek>(reader: image::io::Reader<R>) -> u64 {
+    let scale = match reader.format() {
+        Some(image::ImageFormat::Jpeg) => 64,
+        _ => 1,
+    };
+
+    reader
+        .into_dimensions()
+        .map(|(width, height)| width as u64 * height as u64 * 4 / scale)
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_memory_budget() {
+    let memory = Arc::new(MemoryBudget {
+        limit: 10,
+        in_use: Mutex::new(0),
+        released: Condvar::new(),
+    });
+
+    // too big image is decoded alone
+    memory.acquire(20);
+    let waiting = {
+        let memory = memory.clone();
+        thread::spawn(move || memory.acquire(5))
+    };
+    thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!waiting.is_finished());
+
+    memory.release(20);
+    waiting.join().unwrap();
+    assert_eq!(5, *memory.in_use.lock().unwrap());
+}
+
+#[test]
+fn test_uneven_workload() {
+    fn hash(path: &Path) -> Result<i64, String> {
+        let id: i64 = path.to_str().unwrap().parse().unwrap();
+        if id % 10 == 0 {
+            thread::sleep(std::time::Duration::from_millis(30));
+        }
+        if id == 7 {
+            panic!("broken image");
+        }
+        Ok(id * 2)
+    }
+
+    let images: Vec<ImageWrapper> = (0..100)
+        .map(|x| ImageWrapper {
+            id: x,
+            path: x.to_string().into(),
+            hash: None,
+        })
+        .collect();
+
+    let mut hashed: Vec<ImageWrapper> = HashPool::new(3, DEFAULT_MEMORY_LIMIT)
+        .start(images, hash)
+        .iter()
+        .map(|x| x.0)
+        .collect();
+    hashed.sort_by_key(|x| x.id);
+
+    assert_eq!(100, hashed.len());
+    assert!(hashed
+        .iter()
+        .all(|x| x.hash == if x.id == 7 { None } else { Some(x.id * 2) }));
+}
+
+#[test]
+fn test_empty_input() {
+    let pool = HashPool::new(4, DEFAULT_MEMORY_LIMIT);
+    assert_eq!(0, pool.start(Vec::new(), |_| Ok(0)).iter().count());
+}
+
+#[test]
+fn test_cancelled_pool() {
+    let control = ScanControl::default();
+    control.cancel();
+
+    let images = vec![ImageWrapper {
+        id: 1,
+        path: "1.jpg".into(),
+        hash: None,
+    }];
+    let pool = HashPool::new(2, DEFAULT_MEMORY_LIMIT).with_control(control);
+    assert_eq!(0, pool.start(images, |_| Ok(0)).iter().count());
+}
+
+#[test]
+fn test_archive_jobs() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let zip_path = root.join("1.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    zip.start_file("broken.jpg", zip::write::FileOptions::default())
+        .unwrap();
+    zip.write_all(b"not an image").unwrap();
+    zip.finish().unwrap();
+
+    let images = ["broken.jpg", "missing.jpg"]
+        .iter()
+        .enumerate()
+        .map(|(id, name)| ImageWrapper {
+            id: id as i64,
+            path: archive::virtual_path(&zip_path, Path::new(name)),
+            hash: None,
+        })
+        .collect();
+
+    // the hash of files is not used for images inside archives
+    let mut hashed: Vec<(ImageWrapper, Result<i64, String>)> =
+        HashPool::new(2, DEFAULT_MEMORY_LIMIT)
+            .start(images, |_| Ok(0))
+            .iter()
+            .collect();
+    hashed.sort_by_key(|x| x.0.id);
+
+    assert_eq!(2, hashed.len());
+    assert!(hashed[0].1.is_err());
+    assert_eq!(Err("not found in the archive".to_string()), hashed[1].1);
+}
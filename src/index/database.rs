@@ -0,0 +1,1047 @@
+use crate::archive;
+use crate::config;
+use crate::hasher::HASH_VERSION;
+use log;
+use sqlx;
+use sqlx::pool::PoolConnection;
+use sqlx::prelude::*;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteJournalMode;
+use sqlx::sqlite::SqlitePool;
+use sqlx::sqlite::SqliteSynchronous;
+use sqlx::Sqlite;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const MAX_PATH_SIZE: usize = 2048;
+
+#[derive(Clone)]
+pub struct Database {
+    connection: SqlitePool,
+}
+
+pub struct AcquiredConnection {
+    connection: PoolConnection<Sqlite>,
+}
+
+pub struct FolderWrapper {
+    pub id: i64,
+    pub path: PathBuf,
+    // disabled folders are kept with their images but not scanned
+    pub enabled: bool,
+    pub last_scan: Option<i64>,
+    pub images_count: i64,
+    // gitignore-style patterns relative to the folder
+    pub excludes: Vec<String>,
+}
+pub struct StatsWrapper {
+    pub folders: i64,
+    pub images: i64,
+    pub hashed: i64,
+    pub protected: i64,
+    // images which could not be decoded
+    pub failed: i64,
+}
+
+pub struct PlanWrapper {
+    pub id: i64,
+    pub created_at: i64,
+    pub applied_at: Option<i64>,
+    pub actions_count: i64,
+}
+
+pub struct PlanActionWrapper {
+    pub id: i64,
+    pub plan_id: i64,
+    pub keep_path: PathBuf,
+    pub remove_path: PathBuf,
+    pub strategy: String,
+    pub reason: String,
+    pub status: String,
+    pub error: Option<String>,
+    // size and modification time of files when the plan was built
+    pub keep_state: Option<String>,
+    pub remove_state: Option<String>,
+}
+
+// one run of `scan_folders`, an unfinished session is continued by the next scan
+pub struct ScanSessionWrapper {
+    pub id: i64,
+    pub started_at: i64,
+    pub updated_at: i64,
+    pub phase: String,
+    pub images_found: i64,
+    pub images_hashed: i64,
+    // folders scanned by the session and those of them which were walked to the end
+    pub folders: Vec<i64>,
+    pub walked_folders: Vec<i64>,
+}
+
+// image without hash with the reason of the last failure
+pub struct FailedImageWrapper {
+    pub id: i64,
+    pub path: PathBuf,
+    pub error: String,
+    pub attempts: i64,
+}
+
+#[derive(Clone)]
+pub struct ImageWrapper {
+    pub id: i64,
+    pub path: PathBuf,
+    pub hash: Option<i64>,
+}
+
+impl Database {
+    // database file and its folder are created if missing.
+    // write-ahead log lets the UI read while a scan writes, and with it
+    // commits do not have to wait for the disk
+    pub async fn open(path: &Path) -> Self {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("Can not create database folder");
+        }
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let connection = SqlitePool::connect_with(options)
+            .await
+            .expect("Can not open sqlite db");
+
+        Database { connection }
+    }
+
+    // location is chosen by environment variable, config file or xdg data folder
+    pub async fn connect_default() -> Self {
+        Self::open(&config::database_path(None)).await
+    }
+
+    pub async fn migrate(&self) {
+        self.connection
+            .execute(
+                " 
+                CREATE TABLE IF NOT EXISTS folders (
+                    id INTEGER PRIMARY KEY,
+                    path TEXT(2048) UNIQUE
+                )
+               ",
+            )
+            .await
+            .expect("Can not create folders table");
+
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS images (
+                    id INTEGER PRIMARY KEY,
+                    path TEXT(2048) UNIQUE,
+                    hash INTEGER(64),
+                    protected INTEGER
+                )
+                ",
+            )
+            .await
+            .expect("Can not create images table");
+
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS plans (
+                    id INTEGER PRIMARY KEY,
+                    created_at INTEGER,
+                    applied_at INTEGER
+                )
+                ",
+            )
+            .await
+            .expect("Can not create plans table");
+
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS plan_actions (
+                    id INTEGER PRIMARY KEY,
+                    plan_id INTEGER REFERENCES plans(id),
+                    keep_path TEXT(2048),
+                    remove_path TEXT(2048),
+                    strategy TEXT,
+                    reason TEXT,
+                    status TEXT,
+                    error TEXT
+                )
+                ",
+            )
+            .await
+            .expect("Can not create plan_actions table");
+
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS scan_sessions (
+                    id INTEGER PRIMARY KEY,
+                    started_at INTEGER,
+                    updated_at INTEGER,
+                    phase TEXT,
+                    images_found INTEGER NOT NULL DEFAULT 0,
+                    images_hashed INTEGER NOT NULL DEFAULT 0
+                )
+                ",
+            )
+            .await
+            .expect("Can not create scan_sessions table");
+
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS scan_session_folders (
+                    session_id INTEGER REFERENCES scan_sessions(id),
+                    folder_id INTEGER,
+                    walked INTEGER NOT NULL DEFAULT 0,
+                    UNIQUE(session_id, folder_id)
+                )
+                ",
+            )
+            .await
+            .expect("Can not create scan_session_folders table");
+
+        self.add_column("folders", "enabled", "INTEGER NOT NULL DEFAULT 1")
+            .await;
+        self.add_column("folders", "last_scan", "INTEGER").await;
+        self.add_column("folders", "excludes", "TEXT NOT NULL DEFAULT ''")
+            .await;
+        self.add_column("plan_actions", "keep_state", "TEXT").await;
+        self.add_column("plan_actions", "remove_state", "TEXT")
+            .await;
+        self.add_column("images", "error", "TEXT").await;
+        self.add_column("images", "attempts", "INTEGER NOT NULL DEFAULT 0")
+            .await;
+        self.add_column("images", "hash_version", "INTEGER NOT NULL DEFAULT 1")
+            .await;
+
+        // hashes of another algorithm can not be compared, such images are hashed again
+        sqlx::query("UPDATE images SET hash = NULL WHERE hash IS NOT NULL AND hash_version != ?")
+            .bind(HASH_VERSION)
+            .execute(&self.connection)
+            .await
+            .expect("Can not reset outdated hashes");
+
+        // paths are stored as raw bytes since names on Linux do not have to be valid UTF-8,
+        // older versions stored them as text
+        for (table, column) in [
+            ("folders", "path"),
+            ("images", "path"),
+            ("plan_actions", "keep_path"),
+            ("plan_actions", "remove_path"),
+        ] {
+            self.connection
+                .execute(
+                    format!(
+                        "UPDATE {0} SET {1} = CAST({1} AS BLOB) WHERE typeof({1}) = 'text'",
+                        table, column
+                    )
+                    .as_str(),
+                )
+                .await
+                .expect("Can not convert paths");
+        }
+    }
+
+    // tables created by older versions get new columns here
+    async fn add_column(&self, table: &str, column: &str, definition: &str) {
+        let columns = sqlx::query(format!("PRAGMA table_info({})", table).as_str())
+            .fetch_all(&self.connection)
+            .await
+            .expect("Can not read table columns");
+
+        if columns.iter().any(|x| x.get::<String, _>("name") == column) {
+            return;
+        }
+
+        self.connection
+            .execute(format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition).as_str())
+            .await
+            .expect("Can not add column");
+    }
+
+    pub async fn get_connection(&self) -> AcquiredConnection {
+        let connection = self.connection.acquire().await.unwrap();
+
+        AcquiredConnection { connection }
+    }
+}
+
+impl AcquiredConnection {
+    // some folders may not be inserted
+    pub async fn insert_folders(&mut self, paths: Vec<PathBuf>) -> Vec<FolderWrapper> {
+        let mut result = Vec::new();
+
+        for path in paths {
+            if path_bytes(&path).len() > MAX_PATH_SIZE {
+                log::warn!(
+                    "{} path is too long and can not be inserted into DB",
+                    path.display()
+                );
+                continue;
+            }
+
+            let record_id = sqlx::query("INSERT INTO folders(path) VALUES (?)")
+                .bind(path_bytes(&path))
+                .execute(&mut self.connection)
+                .await;
+
+            if let Ok(record_id) = record_id {
+                result.push(FolderWrapper {
+                    id: record_id.last_insert_rowid(),
+                    path: path.clone(),
+                    enabled: true,
+                    last_scan: None,
+                    images_count: 0,
+                    excludes: Vec::new(),
+                });
+            }
+        }
+
+        result
+    }
+
+    // `true` for a new image, known images are left as they are
+    pub async fn insert_image(&mut self, path: &Path) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO images(path, protected) VALUES(?, 0) ON CONFLICT(path) DO NOTHING",
+        )
+        .bind(path_bytes(path))
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // images are inserted in one transaction, returns paths which were not known before
+    pub async fn insert_images(&mut self, paths: &[PathBuf]) -> Result<Vec<PathBuf>, sqlx::Error> {
+        let mut transaction = self.connection.begin().await?;
+        let mut result = Vec::new();
+
+        for path in paths {
+            let inserted = sqlx::query(
+                "INSERT INTO images(path, protected) VALUES(?, 0) ON CONFLICT(path) DO NOTHING",
+            )
+            .bind(path_bytes(path))
+            .execute(&mut transaction)
+            .await?;
+
+            if inserted.rows_affected() > 0 {
+                result.push(path.clone());
+            }
+        }
+
+        transaction.commit().await?;
+
+        Ok(result)
+    }
+
+    // images which failed `max_attempts` times are skipped, `None` takes all of them
+    pub async fn get_non_hashed_images(
+        &mut self,
+        max_attempts: Option<i64>,
+    ) -> Result<Vec<ImageWrapper>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, path, hash FROM images WHERE hash IS NULL AND (?1 IS NULL OR attempts < ?1)",
+        )
+        .bind(max_attempts)
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let mut result = Vec::with_capacity(rows.len());
+
+        for row in rows.iter() {
+            result.push(ImageWrapper::from_row(&row));
+        }
+
+        Ok(result)
+    }
+
+    // hashes by image id, stored in one transaction. failures are counted,
+    // the last reason is kept until the image is hashed
+    pub async fn update_image_hashes(
+        &mut self,
+        hashes: &[(i64, Result<i64, String>)],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.connection.begin().await?;
+
+        for (id, hash) in hashes {
+            let query = match hash {
+                Ok(hash) => {
+                    sqlx::query(
+                        "UPDATE images SET hash = ?, hash_version = ?, error = NULL, attempts = 0 WHERE id = ?",
+                    )
+                    .bind(hash)
+                    .bind(HASH_VERSION)
+                }
+                Err(error) => sqlx::query(
+                    "UPDATE images SET hash = NULL, error = ?, attempts = attempts + 1 WHERE id = ?",
+                )
+                .bind(error),
+            };
+            query.bind(id).execute(&mut transaction).await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_failed_images(&mut self) -> Result<Vec<FailedImageWrapper>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, path, error, attempts FROM images
+            WHERE hash IS NULL AND error IS NOT NULL
+            ORDER BY path",
+        )
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let mut result = Vec::with_capacity(rows.len());
+
+        for row in rows.iter() {
+            result.push(FailedImageWrapper {
+                id: row.get("id"),
+                path: path_from_row(row, "path"),
+                error: row.get("error"),
+                attempts: row.get("attempts"),
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub async fn get_image(&mut self, path: &Path) -> Result<Option<ImageWrapper>, sqlx::Error> {
+        let row = sqlx::query("SELECT id, path, hash FROM images WHERE path = ?")
+            .bind(path_bytes(path))
+            .fetch_optional(&mut self.connection)
+            .await?;
+
+        Ok(row.map(|x| ImageWrapper::from_row(&x)))
+    }
+
+    // removes the image with this path or all images inside the folder or archive with this path
+    pub async fn remove_images(&mut self, path: &Path) -> Result<u64, sqlx::Error> {
+        let archive_prefix = archive::virtual_path(path, Path::new(""));
+        let result = sqlx::query(
+            "
+            DELETE FROM images
+            WHERE path = ?1
+              OR (
+                substr(path, 1, length(?1)) = ?1
+                AND substr(path, length(?1) + 1, 1) = X'2F'
+              )
+              OR substr(path, 1, length(?2)) = ?2
+            ",
+        )
+        .bind(path_bytes(path))
+        .bind(path_bytes(&archive_prefix))
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // images whose path starts with these bytes are hashed again by the next scan
+    pub async fn reset_image_hashes_in(&mut self, prefix: &Path) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE images SET hash = NULL, error = NULL, attempts = 0
+            WHERE substr(path, 1, length(?1)) = ?1",
+        )
+        .bind(path_bytes(prefix))
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // removes images of the archive which it does not contain anymore, returns their paths
+    pub async fn remove_missing_archive_images(
+        &mut self,
+        path: &Path,
+        listed: &[PathBuf],
+    ) -> Result<Vec<PathBuf>, sqlx::Error> {
+        let archive_prefix = archive::virtual_path(path, Path::new(""));
+        let rows =
+            sqlx::query("SELECT id, path, hash FROM images WHERE substr(path, 1, length(?1)) = ?1")
+                .bind(path_bytes(&archive_prefix))
+                .fetch_all(&mut self.connection)
+                .await?;
+
+        let listed: HashSet<&Path> = listed.iter().map(|x| x.as_path()).collect();
+        let missing: Vec<ImageWrapper> = rows
+            .iter()
+            .map(ImageWrapper::from_row)
+            .filter(|x| !listed.contains(x.path.as_path()))
+            .collect();
+
+        let mut transaction = self.connection.begin().await?;
+        for image in missing.iter() {
+            sqlx::query("DELETE FROM images WHERE id = ?")
+                .bind(image.id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(missing.into_iter().map(|x| x.path).collect())
+    }
+
+    // whether any image path starts with these bytes
+    pub async fn has_images_in(&mut self, prefix: &Path) -> Result<bool, sqlx::Error> {
+        let row =
+            sqlx::query("SELECT id FROM images WHERE substr(path, 1, length(?1)) = ?1 LIMIT 1")
+                .bind(path_bytes(prefix))
+                .fetch_optional(&mut self.connection)
+                .await?;
+
+        Ok(row.is_some())
+    }
+
+    // not reviewed images with the same hash, empty if the image itself was reviewed
+    pub async fn get_duplicates_of(
+        &mut self,
+        image_id: i64,
+    ) -> Result<Vec<ImageWrapper>, sqlx::Error> {
+        let rows = sqlx::query(
+            "
+            SELECT
+              other.id, other.path, other.hash
+            FROM images AS image
+            JOIN images AS other ON other.hash = image.hash AND other.id != image.id
+            WHERE image.id = ?
+              AND image.hash IS NOT NULL AND image.hash != 0
+              AND image.protected = FALSE AND other.protected = FALSE
+            ORDER BY other.id
+            ",
+        )
+        .bind(image_id)
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        Ok(rows.iter().map(ImageWrapper::from_row).collect())
+    }
+
+    pub async fn get_all_folders(&mut self) -> Result<Vec<FolderWrapper>, sqlx::Error> {
+        let mut result = Vec::new();
+        let query_result = sqlx::query(
+            "
+            SELECT
+              id, path, enabled, last_scan, excludes,
+              (
+                SELECT count(images.id)
+                FROM images
+                WHERE substr(images.path, 1, length(folders.path)) = folders.path
+                  AND substr(images.path, length(folders.path) + 1, 1) = X'2F'
+              ) AS images_count
+            FROM folders
+            ORDER BY path
+            ",
+        )
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        for row in query_result.iter() {
+            result.push(FolderWrapper {
+                id: row.get("id"),
+                path: path_from_row(row, "path"),
+                enabled: row.get("enabled"),
+                last_scan: row.get("last_scan"),
+                images_count: row.get("images_count"),
+                excludes: row
+                    .get::<String, _>("excludes")
+                    .lines()
+                    .map(|x| x.to_string())
+                    .collect(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub async fn set_folder_enabled(&mut self, id: i64, enabled: bool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE folders SET enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_folder_excludes(
+        &mut self,
+        id: i64,
+        excludes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE folders SET excludes = ? WHERE id = ?")
+            .bind(excludes.join("\n"))
+            .bind(id)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn mark_folder_scanned(
+        &mut self,
+        id: i64,
+        scanned_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE folders SET last_scan = ? WHERE id = ?")
+            .bind(scanned_at)
+            .bind(id)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+
+    // images of the folder are removed too, unless another registered folder contains them
+    pub async fn remove_folder(&mut self, id: i64) -> Result<(), sqlx::Error> {
+        let mut transaction = self.connection.begin().await?;
+
+        sqlx::query(
+            "
+            DELETE FROM images
+            WHERE EXISTS (
+                SELECT id
+                FROM folders
+                WHERE id = ?1
+                  AND substr(images.path, 1, length(folders.path)) = folders.path
+                  AND substr(images.path, length(folders.path) + 1, 1) = X'2F'
+              )
+              AND NOT EXISTS (
+                SELECT id
+                FROM folders
+                WHERE id != ?1
+                  AND substr(images.path, 1, length(folders.path)) = folders.path
+                  AND substr(images.path, length(folders.path) + 1, 1) = X'2F'
+              )
+            ",
+        )
+        .bind(id)
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query("DELETE FROM scan_session_folders WHERE folder_id = ?")
+            .bind(id)
+            .execute(&mut transaction)
+            .await?;
+
+        sqlx::query("DELETE FROM folders WHERE id = ?")
+            .bind(id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_duplicates(
+        &mut self,
+    ) -> Result<Option<(ImageWrapper, ImageWrapper)>, sqlx::Error> {
+        let query_result = sqlx::query(
+            "
+            SELECT 
+              id, path, hash
+            FROM images 
+            WHERE hash IN (
+                SELECT hash 
+                FROM images 
+                WHERE hash IS NOT NULL AND hash != 0 AND protected = FALSE
+                GROUP BY hash 
+                HAVING count(id) > 1 
+                LIMIT 1
+            ) AND protected = FALSE
+            LIMIT 2;
+            ",
+        )
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        if query_result.len() < 2 {
+            return Ok(None);
+        }
+
+        Ok(Some((
+            ImageWrapper::from_row(&query_result[0]),
+            ImageWrapper::from_row(&query_result[1]),
+        )))
+    }
+
+    // all not reviewed images which share their hash with another one, grouped by hash
+    pub async fn get_duplicate_groups(&mut self) -> Result<Vec<Vec<ImageWrapper>>, sqlx::Error> {
+        let query_result = sqlx::query(
+            "
+            SELECT
+              id, path, hash
+            FROM images
+            WHERE hash IN (
+                SELECT hash
+                FROM images
+                WHERE hash IS NOT NULL AND hash != 0 AND protected = FALSE
+                GROUP BY hash
+                HAVING count(id) > 1
+            ) AND protected = FALSE
+            ORDER BY hash, id;
+            ",
+        )
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let mut result: Vec<Vec<ImageWrapper>> = Vec::new();
+
+        for row in query_result.iter() {
+            let image = ImageWrapper::from_row(row);
+            match result.last_mut() {
+                Some(group) if group[0].hash == image.hash => group.push(image),
+                _ => result.push(vec![image]),
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub async fn mark_protected(&mut self, path: &Path) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE images SET protected=TRUE WHERE path = ?")
+            .bind(path_bytes(path))
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_stats(&mut self) -> Result<StatsWrapper, sqlx::Error> {
+        let row = sqlx::query(
+            "
+            SELECT
+              (SELECT count(id) FROM folders) AS folders,
+              (SELECT count(id) FROM images) AS images,
+              (SELECT count(id) FROM images WHERE hash IS NOT NULL) AS hashed,
+              (SELECT count(id) FROM images WHERE protected = TRUE) AS protected,
+              (SELECT count(id) FROM images WHERE hash IS NULL AND error IS NOT NULL) AS failed
+            ",
+        )
+        .fetch_one(&mut self.connection)
+        .await?;
+
+        Ok(StatsWrapper {
+            folders: row.get("folders"),
+            images: row.get("images"),
+            hashed: row.get("hashed"),
+            protected: row.get("protected"),
+            failed: row.get("failed"),
+        })
+    }
+
+    // plan and its actions are inserted in one transaction, `id` and `plan_id` of actions are ignored
+    pub async fn insert_plan(
+        &mut self,
+        created_at: i64,
+        actions: &[PlanActionWrapper],
+    ) -> Result<i64, sqlx::Error> {
+        let mut transaction = self.connection.begin().await?;
+
+        let plan_id = sqlx::query("INSERT INTO plans(created_at) VALUES (?)")
+            .bind(created_at)
+            .execute(&mut transaction)
+            .await?
+            .last_insert_rowid();
+
+        for action in actions {
+            sqlx::query(
+                "INSERT INTO plan_actions(
+                    plan_id, keep_path, remove_path, strategy, reason, status, error, keep_state, remove_state
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(plan_id)
+            .bind(path_bytes(&action.keep_path))
+            .bind(path_bytes(&action.remove_path))
+            .bind(&action.strategy)
+            .bind(&action.reason)
+            .bind(&action.status)
+            .bind(&action.error)
+            .bind(&action.keep_state)
+            .bind(&action.remove_state)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(plan_id)
+    }
+
+    // newest plans first
+    pub async fn get_plans(&mut self) -> Result<Vec<PlanWrapper>, sqlx::Error> {
+        let query_result = sqlx::query(
+            "
+            SELECT
+              plans.id, plans.created_at, plans.applied_at, count(plan_actions.id) AS actions_count
+            FROM plans
+            LEFT JOIN plan_actions ON plan_actions.plan_id = plans.id
+            GROUP BY plans.id
+            ORDER BY plans.id DESC
+            ",
+        )
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let mut result = Vec::with_capacity(query_result.len());
+
+        for row in query_result.iter() {
+            result.push(PlanWrapper {
+                id: row.get("id"),
+                created_at: row.get("created_at"),
+                applied_at: row.get("applied_at"),
+                actions_count: row.get("actions_count"),
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub async fn get_plan_actions(
+        &mut self,
+        plan_id: i64,
+    ) -> Result<Vec<PlanActionWrapper>, sqlx::Error> {
+        let query_result = sqlx::query(
+            "SELECT
+              id, plan_id, keep_path, remove_path, strategy, reason, status, error, keep_state, remove_state
+            FROM plan_actions WHERE plan_id = ? ORDER BY id",
+        )
+        .bind(plan_id)
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let mut result = Vec::with_capacity(query_result.len());
+
+        for row in query_result.iter() {
+            result.push(PlanActionWrapper {
+                id: row.get("id"),
+                plan_id: row.get("plan_id"),
+                keep_path: path_from_row(row, "keep_path"),
+                remove_path: path_from_row(row, "remove_path"),
+                strategy: row.get("strategy"),
+                reason: row.get("reason"),
+                status: row.get("status"),
+                error: row.get("error"),
+                keep_state: row.get("keep_state"),
+                remove_state: row.get("remove_state"),
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub async fn update_plan_action_status(
+        &mut self,
+        id: i64,
+        status: &str,
+        error: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE plan_actions SET status = ?, error = ? WHERE id = ?")
+            .bind(status)
+            .bind(error)
+            .bind(id)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_scan_session(
+        &mut self,
+        started_at: i64,
+        phase: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let session_id = sqlx::query(
+            "INSERT INTO scan_sessions(started_at, updated_at, phase) VALUES (?, ?, ?)",
+        )
+        .bind(started_at)
+        .bind(started_at)
+        .bind(phase)
+        .execute(&mut self.connection)
+        .await?
+        .last_insert_rowid();
+
+        Ok(session_id)
+    }
+
+    // folders already added to the session keep their progress
+    pub async fn add_scan_session_folders(
+        &mut self,
+        session_id: i64,
+        folder_ids: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.connection.begin().await?;
+
+        for folder_id in folder_ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO scan_session_folders(session_id, folder_id) VALUES (?, ?)",
+            )
+            .bind(session_id)
+            .bind(folder_id)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_scan_session_folder_walked(
+        &mut self,
+        session_id: i64,
+        folder_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scan_session_folders SET walked = 1 WHERE session_id = ? AND folder_id = ?",
+        )
+        .bind(session_id)
+        .bind(folder_id)
+        .execute(&mut self.connection)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_scan_session(
+        &mut self,
+        session: &ScanSessionWrapper,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scan_sessions
+            SET updated_at = ?, phase = ?, images_found = ?, images_hashed = ?
+            WHERE id = ?",
+        )
+        .bind(session.updated_at)
+        .bind(&session.phase)
+        .bind(session.images_found)
+        .bind(session.images_hashed)
+        .bind(session.id)
+        .execute(&mut self.connection)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_last_scan_session(
+        &mut self,
+    ) -> Result<Option<ScanSessionWrapper>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT id, started_at, updated_at, phase, images_found, images_hashed
+            FROM scan_sessions ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(&mut self.connection)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let mut session = ScanSessionWrapper {
+            id: row.get("id"),
+            started_at: row.get("started_at"),
+            updated_at: row.get("updated_at"),
+            phase: row.get("phase"),
+            images_found: row.get("images_found"),
+            images_hashed: row.get("images_hashed"),
+            folders: Vec::new(),
+            walked_folders: Vec::new(),
+        };
+
+        let folders = sqlx::query(
+            "SELECT folder_id, walked FROM scan_session_folders WHERE session_id = ? ORDER BY rowid",
+        )
+        .bind(session.id)
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        for row in folders.iter() {
+            let folder_id: i64 = row.get("folder_id");
+            session.folders.push(folder_id);
+            if row.get::<i64, _>("walked") != 0 {
+                session.walked_folders.push(folder_id);
+            }
+        }
+
+        Ok(Some(session))
+    }
+
+    pub async fn mark_plan_applied(
+        &mut self,
+        plan_id: i64,
+        applied_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE plans SET applied_at = ? WHERE id = ?")
+            .bind(applied_at)
+            .bind(plan_id)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(())
+    }
+}
+
+impl ImageWrapper {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            path: path_from_row(row, "path"),
+            hash: row.get("hash"),
+        }
+    }
+}
+
+fn path_bytes(path: &Path) -> &[u8] {
+    path.as_os_str().as_bytes()
+}
+
+fn path_from_row(row: &sqlx::sqlite::SqliteRow, column: &str) -> PathBuf {
+    PathBuf::from(OsString::from_vec(row.get(column)))
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// "YYYY-MM-DD HH:MM" in UTC, without pulling a date crate for a single label
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+
+    // civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+    assert_eq!(format_timestamp(951782400), "2000-02-29 00:00");
+    assert_eq!(format_timestamp(1700000000), "2023-11-14 22:13");
+}
@@ -0,0 +1,13 @@
+mod database;
+
+pub use database::format_timestamp;
+pub use database::unix_now;
+pub use database::AcquiredConnection;
+pub use database::Database;
+pub use database::FailedImageWrapper;
+pub use database::FolderWrapper;
+pub use database::ImageWrapper;
+pub use database::PlanActionWrapper;
+pub use database::PlanWrapper;
+pub use database::ScanSessionWrapper;
+pub use database::StatsWrapper;
@@ -0,0 +1,30 @@
+//! Search of duplicated images.
+//!
+//! * [`scanner`] walks registered folders, stores found images in the index and hashes them,
+//!   scans can be paused, cancelled and continued later.
+//! * [`watcher`] follows changes in registered folders with inotify between scans.
+//! * [`archive`] reads images inside ZIP and TAR archives by their virtual paths.
+//! * [`hasher`] calculates perceptual hashes, equal hashes mean duplicated images.
+//! * [`index`] is the sqlite database with folders, images, scan sessions and resolution plans.
+//! * [`config`] reads the config file with scan settings and default excludes, finds
+//!   database files of libraries and the thumbnail cache.
+//! * [`resolver`] decides which image of a duplicate group is kept and removes the others.
+//! * [`report`] exports duplicate groups as JSON, CSV or an HTML page with thumbnails.
+//! * [`thumbnail`] keeps previews of images in the freedesktop thumbnail cache.
+//!
+//! The GTK application and the command line tool are built on top of this library,
+//! GTK itself is needed only with the `gui` feature.
+
+pub mod archive;
+pub mod config;
+mod filesystem;
+pub mod hasher;
+pub mod index;
+pub mod report;
+pub mod resolver;
+pub mod scanner;
+pub mod thumbnail;
+pub mod watcher;
+
+pub use index::Database;
+pub use resolver::ResolutionStrategy;
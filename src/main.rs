@@ -1,13 +1,14 @@
-mod database;
-mod filesystem;
 mod ui;
 
-use database::Database;
+use deduplicator::config;
+use deduplicator::index::Database;
+use deduplicator::thumbnail;
 use gtk;
 use gtk::glib;
 use gtk::prelude::*;
 use gtk::Application;
 use simple_logger::SimpleLogger;
+use std::thread;
 use tokio;
 use ui::window::build_ui;
 
@@ -18,6 +19,7 @@ async fn main() -> glib::ExitCode {
     SimpleLogger::new().init().unwrap();
 
     initialize_db().await;
+    thread::spawn(prune_thumbnails);
 
     let app = Application::new(Some(APP_ID), Default::default());
     app.connect_activate(build_ui);
@@ -26,6 +28,15 @@ async fn main() -> glib::ExitCode {
 }
 
 async fn initialize_db() {
-    let database = Database::connect_default().await;
+    let database = Database::open(&config::library_database_path(&config::current_library())).await;
     database.migrate().await;
 }
+
+// thumbnails of deleted images are left behind by resolution and by other applications
+fn prune_thumbnails() {
+    match thumbnail::prune_thumbnails(&config::thumbnails_dir()) {
+        Ok(removed) if removed > 0 => log::info!("Removed {} outdated thumbnails", removed),
+        Ok(_) => {}
+        Err(err) => log::warn!("Can not prune thumbnails: {}", err),
+    }
+}
@@ -0,0 +1,172 @@
+use super::ReportGroup;
+use crate::archive::open_image;
+use crate::filesystem::format_bytes;
+use crate::resolver::relative_path;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const THUMBNAIL_SIZE: u32 = 200;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+section { border-top: 1px solid #ccc; padding: 1em 0; }
+.images { display: flex; flex-wrap: wrap; gap: 1em; }
+figure { margin: 0; width: 220px; padding: 8px; border: 2px solid transparent; }
+figure.keep { border-color: #2a2; }
+figure img { max-width: 200px; max-height: 200px; }
+figcaption { font-size: 0.85em; word-break: break-all; }
+.keep-label { color: #2a2; font-weight: bold; }
+";
+
+// thumbnails are written into `dir` and the page refers to them as `link`
+pub struct ThumbnailsFolder {
+    pub dir: PathBuf,
+    pub link: PathBuf,
+}
+
+// writes report into `output`, thumbnails are put into `thumbnails_dir` or embedded into the page
+pub fn write_html_file(
+    output: &Path,
+    groups: &[ReportGroup],
+    thumbnails_dir: Option<&Path>,
+) -> io::Result<()> {
+    let mut file = fs::File::create(output)?;
+
+    let thumbnails = match thumbnails_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            let page_dir = fs::canonicalize(output)?
+                .parent()
+                .map(|x| x.to_path_buf())
+                .unwrap_or_default();
+
+            Some(ThumbnailsFolder {
+                dir: dir.to_path_buf(),
+                link: relative_path(&page_dir, &fs::canonicalize(dir)?),
+            })
+        }
+        None => None,
+    };
+
+    write_html(&mut file, groups, thumbnails.as_ref())
+}
+
+pub fn write_html(
+    writer: &mut impl Write,
+    groups: &[ReportGroup],
+    thumbnails: Option<&ThumbnailsFolder>,
+) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Duplicated images</title>")?;
+    writeln!(writer, "<style>{}</style>", STYLE)?;
+    writeln!(writer, "</head><body>")?;
+    writeln!(writer, "<h1>Duplicated images</h1>")?;
+    writeln!(writer, "<p>{} groups</p>", groups.len())?;
+
+    for (i, group) in groups.iter().enumerate() {
+        writeln!(writer, "<section>")?;
+        writeln!(
+            writer,
+            "<h2>Group {} <code>{:016x}</code></h2>",
+            i + 1,
+            group.hash
+        )?;
+        writeln!(writer, "<div class=\"images\">")?;
+
+        for (j, image) in group.images.iter().enumerate() {
+            let is_keeper = j == group.keeper;
+            let thumbnail_name = format!("{}-{}.png", i + 1, j + 1);
+
+            writeln!(
+                writer,
+                "<figure{}>",
+                if is_keeper { " class=\"keep\"" } else { "" }
+            )?;
+
+            match thumbnail_source(&image.path, &thumbnail_name, thumbnails) {
+                Some(source) => writeln!(
+                    writer,
+                    "<img src=\"{}\" alt=\"{}\">",
+                    html_escape(&source),
+                    html_escape(&image.path.to_string_lossy())
+                )?,
+                None => writeln!(writer, "<p>No preview</p>")?,
+            }
+
+            writeln!(writer, "<figcaption>")?;
+            if is_keeper {
+                writeln!(
+                    writer,
+                    "<span class=\"keep-label\">Keep</span>: {}<br>",
+                    html_escape(&group.keeper_reason)
+                )?;
+            }
+            writeln!(writer, "{}<br>", html_escape(&image.path.to_string_lossy()))?;
+            writeln!(
+                writer,
+                "{}x{}, {}, distance {}",
+                image.width,
+                image.height,
+                format_bytes(image.size),
+                image.distance
+            )?;
+            writeln!(writer, "</figcaption>")?;
+            writeln!(writer, "</figure>")?;
+        }
+
+        writeln!(writer, "</div>")?;
+        writeln!(writer, "</section>")?;
+    }
+
+    writeln!(writer, "</body></html>")
+}
+
+// link to the saved thumbnail or data uri with embedded one, `None` if image can not be decoded
+fn thumbnail_source(
+    path: &Path,
+    thumbnail_name: &str,
+    thumbnails: Option<&ThumbnailsFolder>,
+) -> Option<String> {
+    let thumbnail = open_image(path)
+        .ok()?
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+    let mut data = Cursor::new(Vec::new());
+    thumbnail
+        .write_to(&mut data, image::ImageOutputFormat::Png)
+        .ok()?;
+
+    match thumbnails {
+        Some(thumbnails) => {
+            fs::write(thumbnails.dir.join(thumbnail_name), data.get_ref()).ok()?;
+            Some(
+                thumbnails
+                    .link
+                    .join(thumbnail_name)
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        }
+        None => Some(format!(
+            "data:image/png;base64,{}",
+            STANDARD.encode(data.get_ref())
+        )),
+    }
+}
+
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,228 @@
+pub mod html;
+
+use crate::hasher::hash_distance;
+use crate::index::ImageWrapper;
+use crate::resolver::policy::Candidate;
+use crate::resolver::policy::Policy;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+    Html,
+}
+
+pub struct ReportImage {
+    pub path: PathBuf,
+    pub hash: i64,
+    // distance to the hash of the first image in group
+    pub distance: u32,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct ReportGroup {
+    pub hash: i64,
+    pub images: Vec<ReportImage>,
+    // index of the image suggested by recommended policy
+    pub keeper: usize,
+    pub keeper_reason: String,
+}
+
+impl ReportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [ReportFormat::Json, ReportFormat::Csv, ReportFormat::Html]
+            .into_iter()
+            .find(|x| x.name() == name)
+    }
+
+    // guessed from file extension, json if extension is unknown
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_lowercase())
+            .and_then(|x| match x.as_str() {
+                "htm" => Some(ReportFormat::Html),
+                x => Self::from_name(x),
+            })
+            .unwrap_or(ReportFormat::Json)
+    }
+}
+
+// reads sizes and dimensions of every image from disk
+pub fn build_report(groups: Vec<Vec<ImageWrapper>>) -> Vec<ReportGroup> {
+    let policy = Policy::recommended();
+    let mut result = Vec::with_capacity(groups.len());
+
+    for group in groups {
+        let group_hash = group.first().and_then(|x| x.hash).unwrap_or(0);
+        let candidates: Vec<Candidate> = group.into_iter().map(Candidate::load).collect();
+        let (keeper, keeper_reason) = policy.choose_keeper(&candidates);
+
+        let images = candidates
+            .into_iter()
+            .map(|x| {
+                let hash = x.image.hash.unwrap_or(0);
+                ReportImage {
+                    path: x.image.path,
+                    hash,
+                    distance: hash_distance(group_hash, hash),
+                    size: x.size,
+                    width: x.width,
+                    height: x.height,
+                }
+            })
+            .collect();
+
+        result.push(ReportGroup {
+            hash: group_hash,
+            images,
+            keeper,
+            keeper_reason,
+        });
+    }
+
+    result
+}
+
+pub fn write_report(
+    writer: &mut impl Write,
+    groups: &[ReportGroup],
+    format: ReportFormat,
+) -> io::Result<()> {
+    match format {
+        ReportFormat::Json => write_json(writer, groups),
+        ReportFormat::Csv => write_csv(writer, groups),
+        ReportFormat::Html => html::write_html(writer, groups, None),
+    }
+}
+
+fn write_json(writer: &mut impl Write, groups: &[ReportGroup]) -> io::Result<()> {
+    writeln!(writer, "{{\"groups\": [")?;
+
+    for (i, group) in groups.iter().enumerate() {
+        writeln!(
+            writer,
+            "  {{\"hash\": \"{:016x}\", \"images\": [",
+            group.hash
+        )?;
+
+        for (j, image) in group.images.iter().enumerate() {
+            writeln!(
+                writer,
+                "    {{\"path\": {}, \"hash\": \"{:016x}\", \"distance\": {}, \"size\": {}, \"width\": {}, \"height\": {}}}{}",
+                json_string(&image.path.to_string_lossy()),
+                image.hash,
+                image.distance,
+                image.size,
+                image.width,
+                image.height,
+                if j + 1 < group.images.len() { "," } else { "" }
+            )?;
+        }
+
+        writeln!(
+            writer,
+            "  ]}}{}",
+            if i + 1 < groups.len() { "," } else { "" }
+        )?;
+    }
+
+    writeln!(writer, "]}}")
+}
+
+fn write_csv(writer: &mut impl Write, groups: &[ReportGroup]) -> io::Result<()> {
+    writeln!(writer, "group,path,hash,distance,size,width,height")?;
+
+    for (i, group) in groups.iter().enumerate() {
+        for image in group.images.iter() {
+            writeln!(
+                writer,
+                "{},{},{:016x},{},{},{},{}",
+                i + 1,
+                csv_field(&image.path.to_string_lossy()),
+                image.hash,
+                image.distance,
+                image.size,
+                image.width,
+                image.height
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+
+    for char in value.chars() {
+        match char {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            char if (char as u32) < 0x20 => {
+                result.push_str(format!("\\u{:04x}", char as u32).as_str())
+            }
+            char => result.push(char),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[test]
+fn test_csv_field() {
+    assert_eq!("a.jpg", csv_field("a.jpg"));
+    assert_eq!("\"a, \"\"b\"\".jpg\"", csv_field("a, \"b\".jpg"));
+}
+
+#[test]
+fn test_json_report() {
+    let groups = vec![ReportGroup {
+        hash: 255,
+        images: vec![ReportImage {
+            path: PathBuf::from("/photos/\"a\".jpg"),
+            hash: 255,
+            distance: 0,
+            size: 10,
+            width: 2,
+            height: 3,
+        }],
+        keeper: 0,
+        keeper_reason: String::new(),
+    }];
+
+    let mut result = Vec::new();
+    write_report(&mut result, &groups, ReportFormat::Json).unwrap();
+
+    assert_eq!(
+        "{\"groups\": [\n  {\"hash\": \"00000000000000ff\", \"images\": [\n    {\"path\": \"/photos/\\\"a\\\".jpg\", \"hash\": \"00000000000000ff\", \"distance\": 0, \"size\": 10, \"width\": 2, \"height\": 3}\n  ]}\n]}\n",
+        String::from_utf8(result).unwrap()
+    );
+}
@@ -0,0 +1,463 @@
+pub mod plan;
+pub mod policy;
+
+use crate::archive;
+use crate::index::AcquiredConnection;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+const COMPARE_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResolutionStrategy {
+    #[default]
+    Delete,
+    Hardlink,
+    Symlink,
+    Reflink,
+}
+
+impl ResolutionStrategy {
+    pub const ALL: [ResolutionStrategy; 4] = [
+        ResolutionStrategy::Delete,
+        ResolutionStrategy::Hardlink,
+        ResolutionStrategy::Symlink,
+        ResolutionStrategy::Reflink,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResolutionStrategy::Delete => "delete",
+            ResolutionStrategy::Hardlink => "hardlink",
+            ResolutionStrategy::Symlink => "symlink",
+            ResolutionStrategy::Reflink => "reflink",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResolutionStrategy::Delete => "Delete",
+            ResolutionStrategy::Hardlink => "Replace with hardlink",
+            ResolutionStrategy::Symlink => "Replace with symlink",
+            ResolutionStrategy::Reflink => "Replace with reflink",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    // removed path keeps existing on disk, it just points to the kept content
+    pub fn keeps_path(&self) -> bool {
+        *self != ResolutionStrategy::Delete
+    }
+}
+
+// removes `removed` or replaces it with a link to `kept`.
+// links are created only for byte-identical files.
+// dry run does every check of a real run but leaves files untouched
+pub fn resolve_duplicate(
+    kept: &Path,
+    removed: &Path,
+    strategy: ResolutionStrategy,
+    dry_run: bool,
+) -> io::Result<()> {
+    check_duplicate(kept, removed, strategy)?;
+
+    if dry_run {
+        return Ok(());
+    }
+
+    if strategy == ResolutionStrategy::Delete {
+        return fs::remove_file(removed);
+    }
+
+    // link is created next to the removed file and renamed over it,
+    // so the removed path never disappears if linking fails
+    let temp_path = temp_path_for(removed);
+    let link_result = match strategy {
+        ResolutionStrategy::Hardlink => fs::hard_link(kept, &temp_path),
+        ResolutionStrategy::Symlink => create_relative_symlink(kept, &temp_path),
+        ResolutionStrategy::Reflink => reflink(kept, &temp_path),
+        ResolutionStrategy::Delete => unreachable!(),
+    };
+
+    if let Err(err) = link_result.and_then(|_| fs::rename(&temp_path, removed)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn check_duplicate(kept: &Path, removed: &Path, strategy: ResolutionStrategy) -> io::Result<()> {
+    // images inside archives are read-only, they can only be kept while a loose copy is deleted
+    if archive::is_virtual_path(removed) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is inside an archive and can not be removed",
+                removed.display()
+            ),
+        ));
+    }
+
+    if archive::is_virtual_path(kept) {
+        if strategy != ResolutionStrategy::Delete {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is inside an archive and links can not point to it",
+                    kept.display()
+                ),
+            ));
+        }
+
+        archive::read_image(kept)?;
+        fs::metadata(removed)?;
+        return Ok(());
+    }
+
+    let kept_metadata = fs::metadata(kept)?;
+    let removed_metadata = fs::metadata(removed)?;
+
+    // a symlink to the removed file would leave no copy behind
+    if (kept_metadata.dev(), kept_metadata.ino())
+        == (removed_metadata.dev(), removed_metadata.ino())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} and {} are the same file",
+                kept.display(),
+                removed.display()
+            ),
+        ));
+    }
+
+    if strategy == ResolutionStrategy::Delete {
+        return Ok(());
+    }
+
+    if strategy == ResolutionStrategy::Hardlink && kept_metadata.dev() != removed_metadata.dev() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} and {} are on different filesystems and can not be hardlinked",
+                kept.display(),
+                removed.display()
+            ),
+        ));
+    }
+
+    if !is_same_content(kept, removed)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} and {} are not identical and can not be linked",
+                kept.display(),
+                removed.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// dry run only checks that image can be removed, nothing is changed on disk or in database
+pub async fn remove_and_protect_image(
+    connection: &mut AcquiredConnection,
+    image_to_protect: &Path,
+    image_to_remove: Option<&Path>,
+    strategy: ResolutionStrategy,
+    dry_run: bool,
+) -> io::Result<()> {
+    if let Some(image_to_remove) = image_to_remove {
+        resolve_duplicate(image_to_protect, image_to_remove, strategy, dry_run)?;
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    connection
+        .mark_protected(image_to_protect)
+        .await
+        .map_err(database_error)?;
+
+    // linked copy has the same content as the protected one now
+    if let Some(image_to_remove) = image_to_remove.filter(|_| strategy.keeps_path()) {
+        connection
+            .mark_protected(image_to_remove)
+            .await
+            .map_err(database_error)?;
+    }
+
+    Ok(())
+}
+
+fn database_error(err: sqlx::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+pub fn is_same_content(left: &Path, right: &Path) -> io::Result<bool> {
+    if fs::metadata(left)?.len() != fs::metadata(right)?.len() {
+        return Ok(false);
+    }
+
+    let mut left_file = fs::File::open(left)?;
+    let mut right_file = fs::File::open(right)?;
+    let mut left_buffer = vec![0u8; COMPARE_BUFFER_SIZE];
+    let mut right_buffer = vec![0u8; COMPARE_BUFFER_SIZE];
+
+    loop {
+        let read = read_full(&mut left_file, &mut left_buffer)?;
+        if read_full(&mut right_file, &mut right_buffer[..read])? != read {
+            return Ok(false);
+        }
+
+        if left_buffer[..read] != right_buffer[..read] {
+            return Ok(false);
+        }
+
+        if read < COMPARE_BUFFER_SIZE {
+            return Ok(true);
+        }
+    }
+}
+
+fn read_full(file: &mut fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+
+    while total < buffer.len() {
+        let read = file.read(&mut buffer[total..])?;
+        if read == 0 {
+            break;
+        }
+        total += read;
+    }
+
+    Ok(total)
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.dedup-tmp", file_name))
+}
+
+// the relative path is built from names only, symlinked folders on the way
+// could make the link point elsewhere, so where it leads is checked
+fn create_relative_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    let link_dir = link.parent().unwrap_or(Path::new(""));
+    std::os::unix::fs::symlink(relative_path(link_dir, target), link)?;
+
+    match (fs::canonicalize(link), fs::canonicalize(target)) {
+        (Ok(resolved), Ok(target)) if resolved == target => Ok(()),
+        _ => {
+            let _ = fs::remove_file(link);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "link from {} does not lead to {}",
+                    link.display(),
+                    target.display()
+                ),
+            ))
+        }
+    }
+}
+
+// path to `target` as seen from `from_dir`, both paths are expected to be absolute
+pub(crate) fn relative_path(from_dir: &Path, target: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = target.components().collect();
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(left, right)| left == right)
+        .count();
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push("..");
+    }
+    for component in to[common..].iter() {
+        result.push(component.as_os_str());
+    }
+
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // FICLONE from linux/fs.h
+    const FICLONE: libc::c_ulong = 0x40049409;
+
+    let source_file = fs::File::open(source)?;
+    let destination_file = fs::File::create(destination)?;
+
+    let result = unsafe {
+        libc::ioctl(
+            destination_file.as_raw_fd(),
+            FICLONE,
+            source_file.as_raw_fd(),
+        )
+    };
+
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        drop(destination_file);
+        let _ = fs::remove_file(destination);
+        return Err(err);
+    }
+
+    destination_file.set_permissions(source_file.metadata()?.permissions())?;
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are supported only on linux",
+    ))
+}
+
+#[test]
+fn test_relative_path() {
+    assert_eq!(
+        PathBuf::from("../b/c.jpg"),
+        relative_path(Path::new("/photos/a"), Path::new("/photos/b/c.jpg"))
+    );
+    assert_eq!(
+        PathBuf::from("c.jpg"),
+        relative_path(Path::new("/photos"), Path::new("/photos/c.jpg"))
+    );
+}
+
+#[test]
+fn test_same_content() {
+    let left = Path::new("tests/test_folders/1.jpg");
+    let right = Path::new("tests/test_folders/2.jpg");
+
+    assert!(is_same_content(left, right).unwrap());
+    assert!(!is_same_content(left, Path::new("src/main.rs")).unwrap());
+}
+
+#[test]
+fn test_resolve_strategies() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let folder = root.join("a");
+    fs::create_dir_all(&folder).unwrap();
+    let content = vec![7u8; COMPARE_BUFFER_SIZE * 2 + 10];
+    let kept = root.join("kept.jpg");
+    fs::write(&kept, &content).unwrap();
+
+    for strategy in ResolutionStrategy::ALL {
+        let removed = folder.join(format!("{}.jpg", strategy.name()));
+        fs::write(&removed, &content).unwrap();
+
+        // dry run leaves the file as it is
+        resolve_duplicate(&kept, &removed, strategy, true).unwrap();
+        assert!(!fs::symlink_metadata(&removed).unwrap().is_symlink());
+
+        let result = resolve_duplicate(&kept, &removed, strategy, false);
+        assert!(!temp_path_for(&removed).exists());
+        let metadata = fs::symlink_metadata(&removed);
+        match strategy {
+            ResolutionStrategy::Delete => assert!(metadata.is_err()),
+            ResolutionStrategy::Hardlink => {
+                result.unwrap();
+                assert_eq!(fs::metadata(&kept).unwrap().ino(), metadata.unwrap().ino());
+            }
+            ResolutionStrategy::Symlink => {
+                result.unwrap();
+                assert!(metadata.unwrap().is_symlink());
+                assert_eq!(
+                    PathBuf::from("../kept.jpg"),
+                    fs::read_link(&removed).unwrap()
+                );
+                assert_eq!(content, fs::read(&removed).unwrap());
+            }
+            // not every filesystem can share extents, the file stays untouched then
+            ResolutionStrategy::Reflink => {
+                assert!(metadata.unwrap().is_file());
+                assert_eq!(content, fs::read(&removed).unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_different_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let kept = root.join("kept.jpg");
+    let removed = root.join("removed.jpg");
+    let mut content = vec![7u8; COMPARE_BUFFER_SIZE + 10];
+    fs::write(&kept, &content).unwrap();
+    // same size, the last byte differs
+    *content.last_mut().unwrap() = 8;
+    fs::write(&removed, &content).unwrap();
+
+    assert!(!is_same_content(&kept, &removed).unwrap());
+    for strategy in [
+        ResolutionStrategy::Hardlink,
+        ResolutionStrategy::Symlink,
+        ResolutionStrategy::Reflink,
+    ] {
+        let err = resolve_duplicate(&kept, &removed, strategy, false).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(content, fs::read(&removed).unwrap());
+    }
+}
+
+#[test]
+fn test_same_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let removed = root.join("removed.jpg");
+    let kept = root.join("kept.jpg");
+    fs::write(&removed, "image").unwrap();
+    std::os::unix::fs::symlink(&removed, &kept).unwrap();
+
+    for strategy in ResolutionStrategy::ALL {
+        let err = resolve_duplicate(&kept, &removed, strategy, false).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!("image", fs::read_to_string(&removed).unwrap());
+    }
+    assert!(resolve_duplicate(&removed, &removed, ResolutionStrategy::Delete, false).is_err());
+}
+
+#[test]
+fn test_symlinked_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let kept = root.join("kept.jpg");
+    fs::write(&kept, "image").unwrap();
+    // `../kept.jpg` from the link folder leads to `deep/kept.jpg`
+    fs::create_dir_all(root.join("deep/nested")).unwrap();
+    std::os::unix::fs::symlink(root.join("deep/nested"), root.join("link")).unwrap();
+    let removed = root.join("link/removed.jpg");
+    fs::write(&removed, "image").unwrap();
+
+    let err = resolve_duplicate(&kept, &removed, ResolutionStrategy::Symlink, false).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    assert!(!fs::symlink_metadata(&removed).unwrap().is_symlink());
+    assert_eq!("image", fs::read_to_string(&removed).unwrap());
+    assert!(!temp_path_for(&removed).exists());
+}
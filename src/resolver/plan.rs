@@ -0,0 +1,227 @@
+use super::policy::Policy;
+use super::remove_and_protect_image;
+use super::ResolutionStrategy;
+use crate::archive;
+use crate::index::unix_now;
+use crate::index::AcquiredConnection;
+use crate::index::PlanActionWrapper;
+use crate::report::csv_field;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+#[derive(Clone)]
+pub struct PlannedAction {
+    pub keep: PathBuf,
+    pub remove: PathBuf,
+    pub strategy: ResolutionStrategy,
+    pub reason: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionStatus {
+    // passed dry run and waits to be applied
+    Pending,
+    // dry run found that action can not be applied
+    Invalid,
+    Done,
+    Failed,
+    // a file changed after the plan was built, nothing is done
+    Changed,
+}
+
+impl ActionStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionStatus::Pending => "pending",
+            ActionStatus::Invalid => "invalid",
+            ActionStatus::Done => "done",
+            ActionStatus::Failed => "failed",
+            ActionStatus::Changed => "changed",
+        }
+    }
+}
+
+// builds plan for all duplicate groups, checks every action with a dry run and stores the plan
+pub async fn build_plan(
+    connection: &mut AcquiredConnection,
+    policy: &Policy,
+) -> Result<(i64, Vec<PlanActionWrapper>), sqlx::Error> {
+    let groups = connection.get_duplicate_groups().await?;
+    let mut actions = Vec::new();
+
+    for action in policy.plan(groups) {
+        let result = remove_and_protect_image(
+            connection,
+            &action.keep,
+            Some(&action.remove),
+            action.strategy,
+            true,
+        )
+        .await;
+        let (status, error) = match result {
+            Ok(()) => (ActionStatus::Pending, None),
+            Err(err) => (ActionStatus::Invalid, Some(err.to_string())),
+        };
+
+        let keep_state = file_state(&action.keep);
+        let remove_state = file_state(&action.remove);
+        actions.push(PlanActionWrapper {
+            id: 0,
+            plan_id: 0,
+            keep_path: action.keep,
+            remove_path: action.remove,
+            strategy: action.strategy.name().to_string(),
+            reason: action.reason,
+            status: status.name().to_string(),
+            error,
+            keep_state,
+            remove_state,
+        });
+    }
+
+    let plan_id = connection.insert_plan(unix_now(), &actions).await?;
+
+    Ok((plan_id, connection.get_plan_actions(plan_id).await?))
+}
+
+pub async fn load_last_plan(
+    connection: &mut AcquiredConnection,
+) -> Result<Option<(i64, Vec<PlanActionWrapper>)>, sqlx::Error> {
+    let plan = connection
+        .get_plans()
+        .await?
+        .into_iter()
+        .find(|x| x.applied_at.is_none());
+
+    match plan {
+        Some(plan) => Ok(Some((plan.id, connection.get_plan_actions(plan.id).await?))),
+        None => Ok(None),
+    }
+}
+
+// applies every pending action of stored plan even if some of them fail.
+// actions whose files changed after the plan was built are skipped
+pub async fn apply_plan(
+    connection: &mut AcquiredConnection,
+    plan_id: i64,
+) -> Result<Vec<PlanActionWrapper>, sqlx::Error> {
+    for action in connection.get_plan_actions(plan_id).await? {
+        if action.status != ActionStatus::Pending.name() {
+            continue;
+        }
+
+        if let Some(changed) = changed_file(&action) {
+            let error = format!("{} changed after the plan was built", changed.display());
+            log::warn!(
+                "Skipping removal of {}: {}",
+                action.remove_path.display(),
+                error
+            );
+            connection
+                .update_plan_action_status(action.id, ActionStatus::Changed.name(), Some(error))
+                .await?;
+            continue;
+        }
+
+        let strategy = ResolutionStrategy::from_name(&action.strategy).unwrap_or_default();
+        let result = remove_and_protect_image(
+            connection,
+            &action.keep_path,
+            Some(&action.remove_path),
+            strategy,
+            false,
+        )
+        .await;
+
+        let (status, error) = match result {
+            Ok(()) => (ActionStatus::Done, None),
+            Err(err) => {
+                log::warn!("Can not remove {}: {}", action.remove_path.display(), err);
+                (ActionStatus::Failed, Some(err.to_string()))
+            }
+        };
+
+        connection
+            .update_plan_action_status(action.id, status.name(), error)
+            .await?;
+    }
+
+    connection.mark_plan_applied(plan_id, unix_now()).await?;
+
+    connection.get_plan_actions(plan_id).await
+}
+
+// size and modification time, images inside archives change with their archive
+fn file_state(path: &Path) -> Option<String> {
+    let path = archive::split_virtual_path(path).map_or(path, |(archive, _)| archive);
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(format!("{}:{}", metadata.len(), modified.as_nanos()))
+}
+
+// kept or removed file which is not the same as when the plan was built,
+// plans without stored states are never applied
+fn changed_file(action: &PlanActionWrapper) -> Option<&Path> {
+    [
+        (&action.keep_path, &action.keep_state),
+        (&action.remove_path, &action.remove_state),
+    ]
+    .into_iter()
+    .find(|(path, state)| state.is_none() || file_state(path) != **state)
+    .map(|(path, _)| path.as_path())
+}
+
+pub fn write_plan_csv(writer: &mut impl Write, actions: &[PlanActionWrapper]) -> io::Result<()> {
+    writeln!(writer, "action,remove,keep,reason,status,error")?;
+
+    for action in actions {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            csv_field(&action.strategy),
+            csv_field(&action.remove_path.to_string_lossy()),
+            csv_field(&action.keep_path.to_string_lossy()),
+            csv_field(&action.reason),
+            csv_field(&action.status),
+            csv_field(action.error.as_deref().unwrap_or("")),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_changed_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let keep = root.join("1.jpg");
+    let remove = root.join("2.jpg");
+    fs::write(&keep, "image").unwrap();
+    fs::write(&remove, "image").unwrap();
+
+    let mut action = PlanActionWrapper {
+        id: 1,
+        plan_id: 1,
+        keep_path: keep.clone(),
+        remove_path: remove.clone(),
+        strategy: ResolutionStrategy::Delete.name().to_string(),
+        reason: String::new(),
+        status: ActionStatus::Pending.name().to_string(),
+        error: None,
+        keep_state: file_state(&keep),
+        remove_state: file_state(&remove),
+    };
+    assert_eq!(None, changed_file(&action));
+
+    fs::write(&remove, "edited image").unwrap();
+    assert_eq!(Some(remove.as_path()), changed_file(&action));
+
+    action.remove_state = file_state(&remove);
+    fs::remove_file(&keep).unwrap();
+    assert_eq!(Some(keep.as_path()), changed_file(&action));
+}
@@ -0,0 +1,269 @@
+use super::plan::PlannedAction;
+use super::ResolutionStrategy;
+use crate::archive;
+use crate::index::ImageWrapper;
+use image;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyRule {
+    HighestResolution,
+    LargestFile,
+    OldestModified,
+    PreferredFolder(String),
+    AvoidCopyName,
+}
+
+// ordered rules, the first rule which can tell images apart decides which one is kept
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    pub rules: Vec<PolicyRule>,
+    pub strategy: ResolutionStrategy,
+}
+
+pub struct Candidate {
+    pub image: ImageWrapper,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,
+    pub modified: Option<u64>,
+}
+
+impl PolicyRule {
+    // `folder` rule takes its path after colon: `folder:/home/user/photos`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (name, argument) = match value.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (value, None),
+        };
+
+        match (name, argument) {
+            ("resolution", None) => Some(PolicyRule::HighestResolution),
+            ("size", None) => Some(PolicyRule::LargestFile),
+            ("oldest", None) => Some(PolicyRule::OldestModified),
+            ("folder", Some(folder)) if !folder.is_empty() => {
+                Some(PolicyRule::PreferredFolder(folder.to_string()))
+            }
+            ("no-copy", None) => Some(PolicyRule::AvoidCopyName),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            PolicyRule::HighestResolution => "highest resolution".to_string(),
+            PolicyRule::LargestFile => "largest file".to_string(),
+            PolicyRule::OldestModified => "oldest modification time".to_string(),
+            PolicyRule::PreferredFolder(folder) => format!("inside {}", folder),
+            PolicyRule::AvoidCopyName => "name is not a copy".to_string(),
+        }
+    }
+
+    // bigger score wins
+    fn score(&self, candidate: &Candidate) -> i128 {
+        match self {
+            PolicyRule::HighestResolution => candidate.width as i128 * candidate.height as i128,
+            PolicyRule::LargestFile => candidate.size as i128,
+            PolicyRule::OldestModified => candidate
+                .modified
+                .map(|x| -(x as i128))
+                .unwrap_or(i128::MIN),
+            PolicyRule::PreferredFolder(folder) => candidate.image.path.starts_with(folder) as i128,
+            PolicyRule::AvoidCopyName => !is_copy_name(&candidate.image.path) as i128,
+        }
+    }
+}
+
+impl Policy {
+    // rules used when user did not choose any
+    pub fn recommended() -> Self {
+        Self {
+            rules: vec![
+                PolicyRule::HighestResolution,
+                PolicyRule::LargestFile,
+                PolicyRule::OldestModified,
+                PolicyRule::AvoidCopyName,
+            ],
+            strategy: ResolutionStrategy::default(),
+        }
+    }
+
+    pub fn plan(&self, groups: Vec<Vec<ImageWrapper>>) -> Vec<PlannedAction> {
+        let mut result = Vec::new();
+
+        for group in groups {
+            // images inside archives are read-only, they are never removed.
+            // loose copies can be deleted in favour of them, but not linked to them
+            let group: Vec<ImageWrapper> = group
+                .into_iter()
+                .filter(|x| {
+                    self.strategy == ResolutionStrategy::Delete
+                        || !archive::is_virtual_path(&x.path)
+                })
+                .collect();
+            if group.len() < 2 {
+                continue;
+            }
+
+            let candidates: Vec<Candidate> = group.into_iter().map(Candidate::load).collect();
+            let (keeper, reason) = self.choose_keeper(&candidates);
+
+            for (i, candidate) in candidates.iter().enumerate() {
+                if i == keeper || archive::is_virtual_path(&candidate.image.path) {
+                    continue;
+                }
+
+                result.push(PlannedAction {
+                    keep: candidates[keeper].image.path.clone(),
+                    remove: candidate.image.path.clone(),
+                    strategy: self.strategy,
+                    reason: reason.clone(),
+                });
+            }
+        }
+
+        result
+    }
+
+    // returns index of the kept candidate and the reason it was chosen
+    pub fn choose_keeper(&self, candidates: &[Candidate]) -> (usize, String) {
+        let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+        let mut reasons = Vec::new();
+
+        for rule in self.rules.iter() {
+            if remaining.len() < 2 {
+                break;
+            }
+
+            let best = remaining
+                .iter()
+                .map(|x| rule.score(&candidates[*x]))
+                .max()
+                .unwrap();
+            let winners: Vec<usize> = remaining
+                .iter()
+                .copied()
+                .filter(|x| rule.score(&candidates[*x]) == best)
+                .collect();
+
+            if winners.len() < remaining.len() {
+                reasons.push(rule.description());
+                remaining = winners;
+            }
+        }
+
+        let reason = if reasons.is_empty() {
+            "no rule could tell images apart, first one is kept".to_string()
+        } else {
+            reasons.join(", ")
+        };
+
+        (remaining[0], reason)
+    }
+}
+
+impl Candidate {
+    pub fn load(image: ImageWrapper) -> Self {
+        if let Some((archive, _)) = archive::split_virtual_path(&image.path) {
+            let modified = modified_time(archive);
+            return Self::load_from_archive(image, modified);
+        }
+
+        let (width, height) = image::image_dimensions(&image.path).unwrap_or((0, 0));
+        let size = fs::metadata(&image.path).map(|x| x.len()).unwrap_or(0);
+        let modified = modified_time(&image.path);
+
+        Self {
+            image,
+            width,
+            height,
+            size,
+            modified,
+        }
+    }
+
+    // image inside archive is read into memory, it has modification time of the archive
+    fn load_from_archive(image: ImageWrapper, modified: Option<u64>) -> Self {
+        let data = archive::read_image(&image.path).unwrap_or_default();
+        let (width, height) = image::io::Reader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|x| x.into_dimensions().ok())
+            .unwrap_or((0, 0));
+
+        Self {
+            image,
+            width,
+            height,
+            size: data.len() as u64,
+            modified,
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs())
+}
+
+fn is_copy_name(path: &Path) -> bool {
+    let name = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    name.contains("copy") || (name.ends_with(')') && name.contains(" ("))
+}
+
+#[test]
+fn test_choose_keeper() {
+    let candidate = |id: i64, path: &str, width: u32, size: u64| Candidate {
+        image: ImageWrapper {
+            id,
+            path: std::path::PathBuf::from(path),
+            hash: Some(1),
+        },
+        width,
+        height: width,
+        size,
+        modified: Some(100),
+    };
+    let candidates = vec![
+        candidate(1, "/photos/a copy.jpg", 100, 10),
+        candidate(2, "/photos/a.jpg", 100, 5),
+        candidate(3, "/archive/a.jpg", 50, 20),
+    ];
+
+    let policy = Policy {
+        rules: vec![PolicyRule::HighestResolution, PolicyRule::AvoidCopyName],
+        strategy: ResolutionStrategy::Delete,
+    };
+    assert_eq!(1, policy.choose_keeper(&candidates).0);
+
+    let policy = Policy {
+        rules: vec![
+            PolicyRule::OldestModified,
+            PolicyRule::PreferredFolder("/archive".to_string()),
+        ],
+        strategy: ResolutionStrategy::Delete,
+    };
+    assert_eq!(2, policy.choose_keeper(&candidates).0);
+
+    assert_eq!(0, Policy::default().choose_keeper(&candidates).0);
+}
+
+#[test]
+fn test_parse_rule() {
+    assert_eq!(
+        Some(PolicyRule::PreferredFolder("/photos".to_string())),
+        PolicyRule::parse("folder:/photos")
+    );
+    assert_eq!(Some(PolicyRule::LargestFile), PolicyRule::parse("size"));
+    assert_eq!(None, PolicyRule::parse("folder"));
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+// shared by the scan, its walker and hashing threads: they stop taking new work while
+// the scan is paused and finish as soon as possible when it is cancelled.
+// work already done is stored, so the next scan continues from there
+#[derive(Clone, Default)]
+pub struct ScanControl {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    cancelled: bool,
+}
+
+impl ScanControl {
+    pub fn pause(&self) {
+        self.update(|x| x.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|x| x.paused = false);
+    }
+
+    pub fn cancel(&self) {
+        self.update(|x| x.cancelled = true);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.0.lock().unwrap().paused
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.0.lock().unwrap().cancelled
+    }
+
+    // blocks while the scan is paused, `false` when it is cancelled
+    pub fn wait(&self) -> bool {
+        let (state, changed) = &*self.state;
+        let mut state = state.lock().unwrap();
+        while state.paused && !state.cancelled {
+            state = changed.wait(state).unwrap();
+        }
+
+        !state.cancelled
+    }
+
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        let (state, changed) = &*self.state;
+        change(&mut state.lock().unwrap());
+        changed.notify_all();
+    }
+}
+
+#[test]
+fn test_scan_control() {
+    let control = ScanControl::default();
+    assert!(control.wait());
+
+    control.pause();
+    let waiting = {
+        let control = control.clone();
+        std::thread::spawn(move || control.wait())
+    };
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!waiting.is_finished());
+
+    // cancelling a paused scan wakes everything up
+    control.cancel();
+    assert!(!waiting.join().unwrap());
+    assert!(!control.wait());
+}
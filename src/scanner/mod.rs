@@ -0,0 +1,424 @@
+mod control;
+mod progress;
+mod session;
+
+use self::progress::ProgressTracker;
+use self::session::Session;
+use crate::archive;
+use crate::config;
+use crate::hasher::pool;
+use crate::hasher::pool::HashPool;
+use crate::hasher::try_image_hash;
+use crate::index::unix_now;
+use crate::index::AcquiredConnection;
+use crate::index::FolderWrapper;
+use crate::index::ImageWrapper;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::UNIX_EPOCH;
+
+pub use self::control::ScanControl;
+pub use self::progress::format_duration;
+pub use self::progress::ScanProgress;
+pub use self::session::describe_session;
+pub use self::session::ScanPhase;
+pub use crate::filesystem::find_files;
+pub use crate::filesystem::format_bytes;
+pub use crate::filesystem::WalkOptions;
+pub use crate::filesystem::Walker;
+
+// rows written to the database in one transaction
+const BATCH_SIZE: usize = 500;
+// hashes are stored at least this often even if the batch is not full
+const BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+// images which could not be decoded this many times are skipped by scans
+// unless `ScanOptions::retry_failed` is set
+pub const MAX_HASH_ATTEMPTS: i64 = 3;
+
+pub enum ScanFolderStatus {
+    // unfinished previous scan is continued, with the number of folders it walked
+    Resumed(usize),
+    ScanningFolders(PathBuf),
+    ImageFound(PathBuf),
+    HashCalculated(PathBuf),
+    // image can not be decoded, with the reason
+    HashFailed(PathBuf, String),
+    // number of rows written in one transaction and how long it took
+    BatchWritten(usize, Duration),
+    // counts of the whole scan, sent a few times per second
+    Progress(ScanProgress),
+    Done,
+    // found images and calculated hashes are stored, the rest is left for the next scan
+    Cancelled,
+    // the index can not be read or written, for example while another process holds
+    // its lock. the scan stops and the next one continues from there
+    Failed(String),
+}
+
+// how folders are walked, see `WalkOptions`, and how images are hashed
+pub struct ScanOptions {
+    pub follow_symlinks: bool,
+    pub same_filesystem: bool,
+    pub dedupe_hardlinks: bool,
+    // images inside ZIP and TAR archives are indexed with virtual paths
+    pub scan_archives: bool,
+    pub hash_threads: usize,
+    // bytes of decoded images held at once by all hashing threads
+    pub hash_memory_limit: u64,
+    // images which failed `MAX_HASH_ATTEMPTS` times are tried again
+    pub retry_failed: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: false,
+            same_filesystem: false,
+            dedupe_hardlinks: true,
+            scan_archives: true,
+            hash_threads: pool::default_threads(),
+            hash_memory_limit: pool::DEFAULT_MEMORY_LIMIT,
+            retry_failed: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    // defaults overridden by the config file
+    pub fn load() -> Self {
+        let config = config::Config::load();
+        let default = Self::default();
+
+        Self {
+            follow_symlinks: config.follow_symlinks.unwrap_or(default.follow_symlinks),
+            same_filesystem: config.same_filesystem.unwrap_or(default.same_filesystem),
+            dedupe_hardlinks: config.dedupe_hardlinks.unwrap_or(default.dedupe_hardlinks),
+            scan_archives: config.scan_archives.unwrap_or(default.scan_archives),
+            hash_threads: config.hash_threads.unwrap_or(default.hash_threads),
+            hash_memory_limit: config
+                .hash_memory_mb
+                .map(|x| x * 1024 * 1024)
+                .unwrap_or(default.hash_memory_limit),
+            retry_failed: default.retry_failed,
+        }
+    }
+}
+
+// walks all registered folders, inserts new images and calculates missing hashes
+pub async fn scan_folders(
+    connection_pool: &mut AcquiredConnection,
+    scan_options: &ScanOptions,
+    control: &ScanControl,
+    on_status: impl Fn(ScanFolderStatus),
+) {
+    let global_excludes = config::global_excludes();
+
+    let folders = match connection_pool.get_all_folders().await {
+        Ok(folders) => folders,
+        Err(err) => {
+            on_status(ScanFolderStatus::Failed(err.to_string()));
+            return;
+        }
+    };
+    let folders: Vec<&FolderWrapper> = folders.iter().filter(|x| x.enabled).collect();
+    // shared by all folders, so nested or linked folders are walked once
+    let walker = Walker::default().with_control(control.clone());
+
+    let mut progress = ProgressTracker::new();
+    let (mut session, resumed) = Session::start(connection_pool, &folders).await;
+    if let Some(walked) = resumed {
+        on_status(ScanFolderStatus::Resumed(walked));
+    }
+
+    for folder in folders {
+        if session.is_walked(folder) {
+            continue;
+        }
+
+        on_status(ScanFolderStatus::ScanningFolders(folder.path.clone()));
+
+        let options = walk_options(folder, scan_options, &global_excludes);
+        // images are inserted in batches while the walk goes on
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for image in walker.walk(folder.path.clone(), options.clone()).iter() {
+            if archive::is_archive(&image) {
+                scan_archive(
+                    connection_pool,
+                    &image,
+                    &options.extensions,
+                    folder.last_scan,
+                    &mut session,
+                    &mut progress,
+                    &on_status,
+                )
+                .await;
+                continue;
+            }
+
+            progress.discovered(1);
+            progress.report(&on_status, false);
+            batch.push(image);
+            if batch.len() >= BATCH_SIZE {
+                store_images(connection_pool, &mut batch, &mut session, &on_status).await;
+            }
+        }
+        store_images(connection_pool, &mut batch, &mut session, &on_status).await;
+
+        // the folder was not walked to the end, so it is not marked as scanned
+        if control.is_cancelled() {
+            session
+                .set_phase(connection_pool, ScanPhase::Cancelled)
+                .await;
+            on_status(ScanFolderStatus::Cancelled);
+            return;
+        }
+
+        if let Err(err) = connection_pool
+            .mark_folder_scanned(folder.id, unix_now())
+            .await
+        {
+            log::warn!(
+                "Can not update scan time of {}: {}",
+                folder.path.display(),
+                err
+            );
+        }
+        session.folder_walked(connection_pool, folder).await;
+    }
+
+    session.set_phase(connection_pool, ScanPhase::Hashing).await;
+    let pool = HashPool::new(scan_options.hash_threads, scan_options.hash_memory_limit)
+        .with_control(control.clone());
+    if let Err(err) = start_hashing(
+        connection_pool,
+        &pool,
+        scan_options.retry_failed,
+        &mut session,
+        &mut progress,
+        &on_status,
+    )
+    .await
+    {
+        on_status(ScanFolderStatus::Failed(err.to_string()));
+        return;
+    }
+
+    if control.is_cancelled() {
+        session
+            .set_phase(connection_pool, ScanPhase::Cancelled)
+            .await;
+        on_status(ScanFolderStatus::Cancelled);
+    } else {
+        session.set_phase(connection_pool, ScanPhase::Done).await;
+        on_status(ScanFolderStatus::Done);
+    }
+}
+
+// global and folder exclusion patterns applied to image files and archives
+pub(crate) fn walk_options(
+    folder: &FolderWrapper,
+    scan_options: &ScanOptions,
+    global_excludes: &[String],
+) -> WalkOptions {
+    let mut extensions = vec![".jpg".to_string(), ".jpeg".to_string(), ".png".to_string()];
+    if scan_options.scan_archives {
+        extensions.extend(archive::EXTENSIONS.iter().map(|x| x.to_string()));
+    }
+
+    WalkOptions {
+        extensions,
+        excludes: global_excludes
+            .iter()
+            .chain(folder.excludes.iter())
+            .cloned()
+            .collect(),
+        follow_symlinks: scan_options.follow_symlinks,
+        same_filesystem: scan_options.same_filesystem,
+        dedupe_hardlinks: scan_options.dedupe_hardlinks,
+    }
+}
+
+// images inside the archive are inserted and hashed later with other images, the pool
+// reads every archive once. archives which did not change since the last scan of their
+// folder are skipped, images of changed ones are hashed again
+async fn scan_archive(
+    connection_pool: &mut AcquiredConnection,
+    path: &Path,
+    extensions: &[String],
+    last_scan: Option<i64>,
+    session: &mut Session,
+    progress: &mut ProgressTracker,
+    on_status: &impl Fn(ScanFolderStatus),
+) {
+    let modified = fs::metadata(path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs() as i64);
+    let prefix = archive::virtual_path(path, Path::new(""));
+    let is_indexed = connection_pool
+        .has_images_in(&prefix)
+        .await
+        .unwrap_or(false);
+
+    if let (Some(modified), Some(last_scan)) = (modified, last_scan) {
+        if modified < last_scan && is_indexed {
+            return;
+        }
+    }
+
+    let mut images = match archive::list_images(path, extensions) {
+        Ok(images) => images,
+        Err(err) => {
+            log::warn!("Can not read archive {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    progress.discovered(images.len() as u64);
+    progress.report(on_status, false);
+
+    if is_indexed {
+        // entries deleted from the archive are forgotten
+        if let Err(err) = connection_pool
+            .remove_missing_archive_images(path, &images)
+            .await
+        {
+            log::warn!("Can not remove old images of {}: {}", path.display(), err);
+        }
+        if let Err(err) = connection_pool.reset_image_hashes_in(&prefix).await {
+            log::warn!("Can not reset hashes of {}: {}", path.display(), err);
+        }
+    }
+    store_images(connection_pool, &mut images, session, on_status).await;
+}
+
+// inserts the batch in one transaction and empties it
+async fn store_images(
+    connection_pool: &mut AcquiredConnection,
+    batch: &mut Vec<PathBuf>,
+    session: &mut Session,
+    on_status: &impl Fn(ScanFolderStatus),
+) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let started_at = Instant::now();
+    let inserted = match connection_pool.insert_images(batch).await {
+        Ok(inserted) => inserted,
+        // one wrong path should not cost the whole batch
+        Err(err) => {
+            log::warn!("Can not insert {} images at once: {}", batch.len(), err);
+            let mut inserted = Vec::new();
+            for image in batch.iter() {
+                match connection_pool.insert_image(image).await {
+                    Ok(true) => inserted.push(image.clone()),
+                    Ok(false) => {}
+                    Err(err) => log::warn!("Can not insert {}: {}", image.display(), err),
+                }
+            }
+            inserted
+        }
+    };
+    report_batch(batch.len(), started_at.elapsed(), on_status);
+    batch.clear();
+
+    session.images_found(connection_pool, inserted.len()).await;
+    for image in inserted {
+        on_status(ScanFolderStatus::ImageFound(image));
+    }
+}
+
+// stores hashes of the batch in one transaction and empties it
+async fn store_hashes(
+    connection_pool: &mut AcquiredConnection,
+    batch: &mut Vec<(ImageWrapper, Result<i64, String>)>,
+    session: &mut Session,
+    on_status: &impl Fn(ScanFolderStatus),
+) -> Result<(), sqlx::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let started_at = Instant::now();
+    let hashes: Vec<(i64, Result<i64, String>)> =
+        batch.iter().map(|x| (x.0.id, x.1.clone())).collect();
+    connection_pool.update_image_hashes(&hashes).await?;
+    report_batch(batch.len(), started_at.elapsed(), on_status);
+
+    let hashed = batch.iter().filter(|x| x.1.is_ok()).count();
+    session.images_hashed(connection_pool, hashed).await;
+    for (image, hash) in batch.drain(..) {
+        match hash {
+            Ok(_) => on_status(ScanFolderStatus::HashCalculated(image.path)),
+            Err(err) => on_status(ScanFolderStatus::HashFailed(image.path, err)),
+        }
+    }
+
+    Ok(())
+}
+
+fn report_batch(rows: usize, elapsed: Duration, on_status: &impl Fn(ScanFolderStatus)) {
+    log::debug!(
+        "{} rows written in {} ms, {:.0} rows/s",
+        rows,
+        elapsed.as_millis(),
+        rows as f64 / elapsed.as_secs_f64().max(0.001)
+    );
+    on_status(ScanFolderStatus::BatchWritten(rows, elapsed));
+}
+
+// every computed hash is stored, the receiver is closed only after the last one.
+// when hashes can not be stored the receiver is dropped, which stops the pool
+async fn start_hashing(
+    connection_pool: &mut AcquiredConnection,
+    pool: &HashPool,
+    retry_failed: bool,
+    session: &mut Session,
+    progress: &mut ProgressTracker,
+    on_status: &impl Fn(ScanFolderStatus),
+) -> Result<(), sqlx::Error> {
+    let max_attempts = if retry_failed {
+        None
+    } else {
+        Some(MAX_HASH_ATTEMPTS)
+    };
+    let images = connection_pool.get_non_hashed_images(max_attempts).await?;
+    let total = images.len();
+    progress.start_hashing(total as u64, on_status);
+    let mut hashed = 0;
+
+    let receiver = pool.start(images, try_image_hash);
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        match receiver.recv_timeout(BATCH_TIMEOUT) {
+            Ok((image, hash)) => {
+                let bytes = fs::metadata(&image.path).map(|x| x.len()).unwrap_or(0);
+                progress.hashed(image.hash, bytes);
+                progress.report(on_status, false);
+                batch.push((image, hash));
+                hashed += 1;
+                if batch.len() < BATCH_SIZE {
+                    continue;
+                }
+            }
+            // slow images should not keep hashes of fast ones unsaved
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        store_hashes(connection_pool, &mut batch, session, on_status).await?;
+    }
+    store_hashes(connection_pool, &mut batch, session, on_status).await?;
+    progress.report(on_status, true);
+
+    if hashed < total && !pool.is_cancelled() {
+        log::warn!("{} of {} images were not hashed", total - hashed, total);
+    }
+
+    Ok(())
+}
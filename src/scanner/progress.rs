@@ -0,0 +1,187 @@
+use super::ScanFolderStatus;
+use super::ScanPhase;
+use crate::filesystem::format_bytes;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+// progress events are sent at most this often, phase changes are sent right away
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Debug)]
+pub struct ScanProgress {
+    pub phase: ScanPhase,
+    // images met by the walk, already known ones included
+    pub discovered: u64,
+    // images to hash in this scan, known when hashing starts
+    pub to_hash: u64,
+    pub hashed: u64,
+    // images which could not be decoded
+    pub failed: u64,
+    // size of hashed files
+    pub bytes: u64,
+    // time spent in the current phase
+    pub elapsed: Duration,
+}
+
+impl ScanProgress {
+    // images per second in the current phase
+    pub fn rate(&self) -> f64 {
+        let done = match self.phase {
+            ScanPhase::Walking => self.discovered,
+            _ => self.hashed + self.failed,
+        };
+
+        done as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    pub fn bytes_rate(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    // part of hashing which is done, `None` while the amount of work is unknown
+    pub fn fraction(&self) -> Option<f64> {
+        if self.phase != ScanPhase::Hashing {
+            return None;
+        }
+        if self.to_hash == 0 {
+            return Some(1.0);
+        }
+
+        Some(((self.hashed + self.failed) as f64 / self.to_hash as f64).min(1.0))
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let left = self.to_hash.saturating_sub(self.hashed + self.failed);
+        let rate = self.rate();
+        if self.phase != ScanPhase::Hashing || rate <= 0.0 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(left as f64 / rate))
+    }
+}
+
+// one line for status bars and terminals
+impl fmt::Display for ScanProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.phase {
+            ScanPhase::Walking => write!(
+                f,
+                "Walking: {} images found, {:.0} images/s",
+                self.discovered,
+                self.rate()
+            ),
+            _ => {
+                write!(
+                    f,
+                    "Hashing: {} of {} images",
+                    self.hashed + self.failed,
+                    self.to_hash
+                )?;
+                if self.failed > 0 {
+                    write!(f, ", {} failed", self.failed)?;
+                }
+                write!(
+                    f,
+                    ", {}, {}/s",
+                    format_bytes(self.bytes),
+                    format_bytes(self.bytes_rate() as u64)
+                )?;
+                match self.eta() {
+                    Some(eta) if self.hashed + self.failed > 0 => {
+                        write!(f, ", {} left", format_duration(eta))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// counts progress of the scan and sends it without flooding the receiver
+pub(crate) struct ProgressTracker {
+    progress: ScanProgress,
+    phase_started: Instant,
+    last_report: Option<Instant>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            progress: ScanProgress {
+                phase: ScanPhase::Walking,
+                discovered: 0,
+                to_hash: 0,
+                hashed: 0,
+                failed: 0,
+                bytes: 0,
+                elapsed: Duration::ZERO,
+            },
+            phase_started: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    pub(crate) fn discovered(&mut self, count: u64) {
+        self.progress.discovered += count;
+    }
+
+    pub(crate) fn hashed(&mut self, hash: Option<i64>, bytes: u64) {
+        match hash {
+            Some(_) => self.progress.hashed += 1,
+            None => self.progress.failed += 1,
+        }
+        self.progress.bytes += bytes;
+    }
+
+    pub(crate) fn start_hashing(&mut self, to_hash: u64, on_status: &impl Fn(ScanFolderStatus)) {
+        self.progress.phase = ScanPhase::Hashing;
+        self.progress.to_hash += to_hash;
+        self.phase_started = Instant::now();
+        self.report(on_status, true);
+    }
+
+    pub(crate) fn report(&mut self, on_status: &impl Fn(ScanFolderStatus), force: bool) {
+        let now = Instant::now();
+        if !force && matches!(self.last_report, Some(x) if now - x < REPORT_INTERVAL) {
+            return;
+        }
+
+        self.last_report = Some(now);
+        self.progress.elapsed = now - self.phase_started;
+        on_status(ScanFolderStatus::Progress(self.progress.clone()));
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{} s", seconds)
+    } else if seconds < 3600 {
+        format!("{} min {} s", seconds / 60, seconds % 60)
+    } else {
+        format!("{} h {} min", seconds / 3600, seconds % 3600 / 60)
+    }
+}
+
+#[test]
+fn test_progress() {
+    let progress = ScanProgress {
+        phase: ScanPhase::Hashing,
+        discovered: 100,
+        to_hash: 40,
+        hashed: 18,
+        failed: 2,
+        bytes: 3 * 1024 * 1024,
+        elapsed: Duration::from_secs(10),
+    };
+
+    assert_eq!(Some(0.5), progress.fraction());
+    assert_eq!(Some(Duration::from_secs(10)), progress.eta());
+    assert_eq!(
+        "Hashing: 20 of 40 images, 2 failed, 3.0 MB, 307.2 KB/s, 10 s left",
+        progress.to_string()
+    );
+    assert_eq!("1 h 1 min", format_duration(Duration::from_secs(3670)));
+}
@@ -0,0 +1,183 @@
+use crate::index::format_timestamp;
+use crate::index::unix_now;
+use crate::index::AcquiredConnection;
+use crate::index::FolderWrapper;
+use crate::index::ScanSessionWrapper;
+
+// counters are stored after this many images and at every phase change
+const SAVE_INTERVAL: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanPhase {
+    Walking,
+    Hashing,
+    Done,
+    Cancelled,
+}
+
+impl ScanPhase {
+    pub const ALL: [ScanPhase; 4] = [
+        ScanPhase::Walking,
+        ScanPhase::Hashing,
+        ScanPhase::Done,
+        ScanPhase::Cancelled,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScanPhase::Walking => "walking",
+            ScanPhase::Hashing => "hashing",
+            ScanPhase::Done => "done",
+            ScanPhase::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+}
+
+// one line about the last scan for status bars
+pub fn describe_session(session: &ScanSessionWrapper) -> String {
+    let state = match ScanPhase::from_name(&session.phase) {
+        Some(ScanPhase::Done) => "finished",
+        Some(ScanPhase::Cancelled) => "cancelled, the next scan continues it",
+        Some(ScanPhase::Walking) => "interrupted while walking folders, the next scan continues it",
+        Some(ScanPhase::Hashing) => "interrupted while hashing, the next scan continues it",
+        None => "in unknown state",
+    };
+
+    format!(
+        "Last scan {}: {}, {} images found, {} hashed",
+        format_timestamp(session.updated_at),
+        state,
+        session.images_found,
+        session.images_hashed
+    )
+}
+
+// progress of the running scan kept in the database. a session which was cancelled or
+// interrupted by a crash is continued: folders walked to the end are not walked again,
+// images without hashes are hashed by every scan anyway
+pub(crate) struct Session {
+    state: ScanSessionWrapper,
+    changes: usize,
+}
+
+impl Session {
+    // with the number of folders walked by the continued session, `None` for a new one
+    pub(crate) async fn start(
+        connection_pool: &mut AcquiredConnection,
+        folders: &[&FolderWrapper],
+    ) -> (Self, Option<usize>) {
+        let previous = connection_pool
+            .get_last_scan_session()
+            .await
+            .unwrap_or_else(|err| {
+                log::warn!("Can not read the last scan session: {}", err);
+                None
+            })
+            .filter(|x| ScanPhase::from_name(&x.phase) != Some(ScanPhase::Done));
+
+        let (state, resumed) = match previous {
+            Some(state) => {
+                let walked = state.walked_folders.len();
+                (state, Some(walked))
+            }
+            None => {
+                let now = unix_now();
+                let id = connection_pool
+                    .insert_scan_session(now, ScanPhase::Walking.name())
+                    .await
+                    .unwrap();
+                let state = ScanSessionWrapper {
+                    id,
+                    started_at: now,
+                    updated_at: now,
+                    phase: ScanPhase::Walking.name().to_string(),
+                    images_found: 0,
+                    images_hashed: 0,
+                    folders: Vec::new(),
+                    walked_folders: Vec::new(),
+                };
+                (state, None)
+            }
+        };
+
+        // folders added since the interrupted scan are scanned by it too
+        let folder_ids: Vec<i64> = folders.iter().map(|x| x.id).collect();
+        if let Err(err) = connection_pool
+            .add_scan_session_folders(state.id, &folder_ids)
+            .await
+        {
+            log::warn!("Can not store folders of the scan session: {}", err);
+        }
+
+        let mut session = Self { state, changes: 0 };
+        session.set_phase(connection_pool, ScanPhase::Walking).await;
+
+        (session, resumed)
+    }
+
+    pub(crate) fn is_walked(&self, folder: &FolderWrapper) -> bool {
+        self.state.walked_folders.contains(&folder.id)
+    }
+
+    pub(crate) async fn folder_walked(
+        &mut self,
+        connection_pool: &mut AcquiredConnection,
+        folder: &FolderWrapper,
+    ) {
+        if let Err(err) = connection_pool
+            .mark_scan_session_folder_walked(self.state.id, folder.id)
+            .await
+        {
+            log::warn!("Can not store progress of the scan: {}", err);
+        }
+        self.state.walked_folders.push(folder.id);
+        self.save(connection_pool).await;
+    }
+
+    pub(crate) async fn images_found(
+        &mut self,
+        connection_pool: &mut AcquiredConnection,
+        count: usize,
+    ) {
+        self.state.images_found += count as i64;
+        self.changed(connection_pool, count).await;
+    }
+
+    pub(crate) async fn images_hashed(
+        &mut self,
+        connection_pool: &mut AcquiredConnection,
+        count: usize,
+    ) {
+        self.state.images_hashed += count as i64;
+        self.changed(connection_pool, count).await;
+    }
+
+    pub(crate) async fn set_phase(
+        &mut self,
+        connection_pool: &mut AcquiredConnection,
+        phase: ScanPhase,
+    ) {
+        self.state.phase = phase.name().to_string();
+        self.save(connection_pool).await;
+    }
+
+    async fn changed(&mut self, connection_pool: &mut AcquiredConnection, count: usize) {
+        self.changes += count;
+        if self.changes >= SAVE_INTERVAL {
+            self.save(connection_pool).await;
+        }
+    }
+
+    async fn save(&mut self, connection_pool: &mut AcquiredConnection) {
+        self.state.updated_at = unix_now();
+        self.changes = 0;
+
+        if let Err(err) = connection_pool.update_scan_session(&self.state).await {
+            log::warn!("Can not store progress of the scan: {}", err);
+        }
+    }
+}
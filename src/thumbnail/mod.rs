@@ -0,0 +1,373 @@
+use crate::archive;
+use image;
+use md5::Digest;
+use md5::Md5;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// thumbnails follow the freedesktop thumbnail specification, so file managers share them:
+// `<cache>/<size>/<md5 of the uri>.png` with the uri and the modification time
+// of the image in text chunks
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+const SOFTWARE_KEY: &str = "Software";
+const SOFTWARE: &str = "deduplicator";
+// temporary files of crashed processes are removed after that time
+const STALE_TEMPORARY_AGE: Duration = Duration::from_secs(60 * 60);
+
+// numbers of temporary files, unique for every thumbnail stored by the process
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThumbnailSize {
+    Normal,
+    Large,
+    XLarge,
+    XXLarge,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 4] = [
+        ThumbnailSize::Normal,
+        ThumbnailSize::Large,
+        ThumbnailSize::XLarge,
+        ThumbnailSize::XXLarge,
+    ];
+
+    // thumbnails fit into a square of this size, smaller images are kept as they are
+    pub fn pixels(&self) -> u32 {
+        match self {
+            ThumbnailSize::Normal => 128,
+            ThumbnailSize::Large => 256,
+            ThumbnailSize::XLarge => 512,
+            ThumbnailSize::XXLarge => 1024,
+        }
+    }
+
+    fn folder(&self) -> &'static str {
+        match self {
+            ThumbnailSize::Normal => "normal",
+            ThumbnailSize::Large => "large",
+            ThumbnailSize::XLarge => "x-large",
+            ThumbnailSize::XXLarge => "xx-large",
+        }
+    }
+}
+
+// preview of the image read from `cache`. a missing thumbnail or one made from another
+// version of the image is decoded again and stored, failures to store it are only logged
+pub fn load_thumbnail(
+    cache: &Path,
+    path: &Path,
+    size: ThumbnailSize,
+) -> image::ImageResult<image::DynamicImage> {
+    let uri = file_uri(path);
+    let cached = cached_path(cache, &uri, size);
+    let mtime = modified(source_of(path));
+
+    if let Some(thumbnail) = mtime.and_then(|x| read_thumbnail(&cached, &uri, x)) {
+        return Ok(thumbnail);
+    }
+
+    let image = archive::open_image_scaled(path, size.pixels() as u16)?;
+    let thumbnail = if image.width() > size.pixels() || image.height() > size.pixels() {
+        image.thumbnail(size.pixels(), size.pixels())
+    } else {
+        image
+    };
+    let thumbnail = thumbnail.to_rgba8();
+
+    // without modification time the thumbnail could not be checked later
+    if let Some(mtime) = mtime {
+        if let Err(err) = store_thumbnail(&cached, &thumbnail, &uri, mtime) {
+            log::warn!("Can not store thumbnail {}: {}", cached.display(), err);
+        }
+    }
+
+    Ok(image::DynamicImage::ImageRgba8(thumbnail))
+}
+
+// `file://` uri with bytes escaped like glib does, otherwise the md5 differs from
+// the one of other applications
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => uri.push(*byte as char),
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b'-' | b'.' | b'/'
+            | b':' | b'=' | b'@' | b'_' | b'~' => uri.push(*byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let escaped = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(escaped.len());
+
+    let mut i = 0;
+    while i < escaped.len() {
+        if escaped[i] == b'%' {
+            let hex = std::str::from_utf8(escaped.get(i + 1..i + 3)?).ok()?;
+            path.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            path.push(escaped[i]);
+            i += 1;
+        }
+    }
+
+    Some(PathBuf::from(OsString::from_vec(path)))
+}
+
+fn cached_path(cache: &Path, uri: &str, size: ThumbnailSize) -> PathBuf {
+    cache
+        .join(size.folder())
+        .join(format!("{:x}.png", Md5::digest(uri.as_bytes())))
+}
+
+// images inside archives change with their archive
+fn source_of(path: &Path) -> &Path {
+    archive::split_virtual_path(path).map_or(path, |(archive, _)| archive)
+}
+
+// seconds since epoch, as `Thumb::MTime` stores them
+fn modified(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs())
+}
+
+// text chunks of the thumbnail, which come before the image data
+fn read_keys(data: impl io::Read) -> Option<Vec<(String, String)>> {
+    let reader = png::Decoder::new(data).read_info().ok()?;
+
+    Some(
+        reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .map(|x| (x.keyword.clone(), x.text.clone()))
+            .collect(),
+    )
+}
+
+// `None` if the thumbnail is missing, broken or made from another version of the image
+fn read_thumbnail(cached: &Path, uri: &str, mtime: u64) -> Option<image::DynamicImage> {
+    let data = fs::read(cached).ok()?;
+    let keys = read_keys(Cursor::new(&data))?;
+    let value = |key: &str| keys.iter().find(|x| x.0 == key).map(|x| x.1.as_str());
+
+    if value(URI_KEY) != Some(uri) || value(MTIME_KEY) != Some(mtime.to_string().as_str()) {
+        return None;
+    }
+
+    image::load_from_memory_with_format(&data, image::ImageFormat::Png).ok()
+}
+
+// the specification asks for folders and files readable only by the user
+fn store_thumbnail(
+    cached: &Path,
+    thumbnail: &image::RgbaImage,
+    uri: &str,
+    mtime: u64,
+) -> io::Result<()> {
+    if let Some(parent) = cached.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+    }
+
+    // written under another name first, so other threads and applications never read
+    // a half written file. threads storing the same thumbnail write their own files
+    let temporary = cached.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result =
+        write_png(&temporary, thumbnail, uri, mtime).and_then(|_| fs::rename(&temporary, cached));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+fn write_png(path: &Path, thumbnail: &image::RgbaImage, uri: &str, mtime: u64) -> io::Result<()> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+
+    let mut encoder = png::Encoder::new(
+        io::BufWriter::new(file),
+        thumbnail.width(),
+        thumbnail.height(),
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (key, value) in [
+        (URI_KEY, uri.to_string()),
+        (MTIME_KEY, mtime.to_string()),
+        (SOFTWARE_KEY, SOFTWARE.to_string()),
+    ] {
+        encoder
+            .add_text_chunk(key.to_string(), value)
+            .map_err(io::Error::other)?;
+    }
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(thumbnail.as_raw())
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+// removes thumbnails of local images which do not exist anymore, as the specification
+// allows any application to do, and temporary files of crashed processes.
+// returns the number of removed files
+pub fn prune_thumbnails(cache: &Path) -> io::Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+
+    for size in ThumbnailSize::ALL {
+        let entries = match fs::read_dir(cache.join(size.folder())) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            let is_stale = if path.extension() == Some("tmp".as_ref()) {
+                let modified = fs::metadata(&path)?.modified()?;
+                now.duration_since(modified).unwrap_or_default() > STALE_TEMPORARY_AGE
+            } else {
+                fs::File::open(&path)
+                    .ok()
+                    .and_then(|x| read_keys(io::BufReader::new(x)))
+                    .and_then(|keys| keys.into_iter().find(|x| x.0 == URI_KEY))
+                    .and_then(|x| path_from_uri(&x.1))
+                    .is_some_and(|x| {
+                        matches!(fs::symlink_metadata(source_of(&x)),
+                            Err(err) if err.kind() == io::ErrorKind::NotFound)
+                    })
+            };
+
+            if is_stale {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+#[test]
+fn test_file_uri() {
+    // the example of the specification
+    let uri = file_uri(Path::new("/home/jens/photos/me.png"));
+    assert_eq!("file:///home/jens/photos/me.png", uri);
+    assert_eq!(
+        PathBuf::from("/cache/normal/c6ee772d9e49320e97ec29a7eb5b1697.png"),
+        cached_path(Path::new("/cache"), &uri, ThumbnailSize::Normal)
+    );
+
+    let path = Path::new(std::ffi::OsStr::from_bytes(
+        b"/photos/a b/caf\xc3\xa9#1.jpg",
+    ));
+    let uri = file_uri(path);
+    assert_eq!("file:///photos/a%20b/caf%C3%A9%231.jpg", uri);
+    assert_eq!(Some(path.to_path_buf()), path_from_uri(&uri));
+}
+
+#[test]
+fn test_thumbnail_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let cache = root.join("cache");
+
+    let path = root.join("1.png");
+    image::RgbImage::from_pixel(1536, 768, image::Rgb([200, 10, 10]))
+        .save(&path)
+        .unwrap();
+
+    let thumbnail = load_thumbnail(&cache, &path, ThumbnailSize::Large).unwrap();
+    assert_eq!((256, 128), (thumbnail.width(), thumbnail.height()));
+    let uri = file_uri(&path);
+    let cached = cached_path(&cache, &uri, ThumbnailSize::Large);
+    let keys = read_keys(fs::File::open(&cached).unwrap()).unwrap();
+    assert!(keys.contains(&(URI_KEY.to_string(), uri.clone())));
+    let mtime = modified(&path).unwrap();
+    assert!(keys.contains(&(MTIME_KEY.to_string(), mtime.to_string())));
+    assert!(read_thumbnail(&cached, &uri, mtime).is_some());
+
+    // a changed image is decoded again
+    image::RgbImage::from_pixel(100, 50, image::Rgb([0, 0, 0]))
+        .save(&path)
+        .unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    assert!(read_thumbnail(&cached, &uri, modified(&path).unwrap()).is_none());
+
+    let thumbnail = load_thumbnail(&cache, &path, ThumbnailSize::Large).unwrap();
+    assert_eq!((100, 50), (thumbnail.width(), thumbnail.height()));
+    assert_eq!((100, 50), image::image_dimensions(&cached).unwrap());
+}
+
+#[test]
+fn test_prune_thumbnails() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+    let cache = root.join("cache");
+
+    let paths: Vec<PathBuf> = (0..2).map(|x| root.join(format!("{}.png", x))).collect();
+    for path in paths.iter() {
+        image::RgbImage::from_pixel(64, 64, image::Rgb([200, 10, 10]))
+            .save(path)
+            .unwrap();
+        load_thumbnail(&cache, path, ThumbnailSize::Normal).unwrap();
+    }
+    fs::remove_file(&paths[1]).unwrap();
+
+    // a temporary file of a crashed process
+    let temporary = cache.join("normal/0.1-0.tmp");
+    fs::File::create(&temporary)
+        .unwrap()
+        .set_modified(SystemTime::now() - STALE_TEMPORARY_AGE * 2)
+        .unwrap();
+
+    assert_eq!(2, prune_thumbnails(&cache).unwrap());
+    assert!(!temporary.exists());
+    let cached = |path: &Path| cached_path(&cache, &file_uri(path), ThumbnailSize::Normal);
+    assert!(cached(&paths[0]).exists());
+    assert!(!cached(&paths[1]).exists());
+
+    assert_eq!(0, prune_thumbnails(&cache).unwrap());
+}
@@ -0,0 +1,99 @@
+use super::processes::get_failed_images;
+use deduplicator::index::FailedImageWrapper;
+use deduplicator::scanner::MAX_HASH_ATTEMPTS;
+use gtk::glib;
+use gtk::prelude::*;
+use std::thread;
+
+// images of the current library which could not be hashed
+#[derive(Clone)]
+pub struct ErrorList {
+    pub expander: gtk::Expander,
+    list_box: gtk::ListBox,
+    status_label: gtk::Label,
+}
+
+impl ErrorList {
+    pub fn new(status_label: gtk::Label) -> Self {
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .build();
+
+        let scroll = gtk::ScrolledWindow::builder()
+            .child(&list_box)
+            .min_content_height(120)
+            .build();
+
+        let expander = gtk::Expander::builder()
+            .label("Errors")
+            .child(&scroll)
+            .build();
+
+        let result = Self {
+            expander,
+            list_box,
+            status_label,
+        };
+
+        result.refresh();
+
+        result
+    }
+
+    pub fn refresh(&self) {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        thread::spawn(move || {
+            sender
+                .send(get_failed_images())
+                .expect("Can not send failed images to main thread");
+        });
+
+        let list_box = self.list_box.clone();
+        let expander = self.expander.clone();
+        let status_label = self.status_label.clone();
+        receiver.attach(None, move |result| {
+            while let Some(row) = list_box.first_child() {
+                list_box.remove(&row);
+            }
+
+            match result {
+                Ok(images) => {
+                    expander.set_label(Some(format!("Errors ({})", images.len()).as_str()));
+                    for image in images.iter() {
+                        list_box.append(&error_row(image));
+                    }
+                }
+                Err(err) => {
+                    status_label.set_label(format!("Can not load failed images: {}", err).as_str());
+                }
+            }
+
+            Continue(false)
+        });
+    }
+}
+
+fn error_row(image: &FailedImageWrapper) -> gtk::Box {
+    let path_label = gtk::Label::builder()
+        .label(image.path.display().to_string().as_str())
+        .halign(gtk::Align::Start)
+        .hexpand(true)
+        .build();
+
+    let attempts = if image.attempts >= MAX_HASH_ATTEMPTS {
+        format!("{} attempts, skipped", image.attempts)
+    } else {
+        format!("{} attempts", image.attempts)
+    };
+    let error_label = gtk::Label::builder()
+        .label(format!("{}  ({})", image.error, attempts).as_str())
+        .halign(gtk::Align::End)
+        .build();
+
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    row.append(&path_label);
+    row.append(&error_label);
+
+    row
+}
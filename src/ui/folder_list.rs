@@ -0,0 +1,214 @@
+use super::processes::get_folders;
+use super::processes::remove_folder;
+use super::processes::set_folder_enabled;
+use super::processes::set_folder_excludes;
+use deduplicator::index::format_timestamp;
+use deduplicator::index::FolderWrapper;
+use gtk;
+use gtk::glib;
+use gtk::prelude::*;
+use std::thread;
+
+// added folders of the current library, they can be disabled or removed
+#[derive(Clone)]
+pub struct FolderList {
+    pub expander: gtk::Expander,
+    list_box: gtk::ListBox,
+    status_label: gtk::Label,
+}
+
+impl FolderList {
+    pub fn new(status_label: gtk::Label) -> Self {
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .build();
+
+        let scroll = gtk::ScrolledWindow::builder()
+            .child(&list_box)
+            .min_content_height(120)
+            .build();
+
+        let expander = gtk::Expander::builder()
+            .label("Folders")
+            .child(&scroll)
+            .build();
+
+        let result = Self {
+            expander,
+            list_box,
+            status_label,
+        };
+
+        result.refresh();
+
+        result
+    }
+
+    pub fn refresh(&self) {
+        refresh_folders(&self.list_box, &self.status_label);
+    }
+}
+
+fn refresh_folders(list_box: &gtk::ListBox, status_label: &gtk::Label) {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+    thread::spawn(move || {
+        sender
+            .send(get_folders())
+            .expect("Can not send folders to main thread");
+    });
+
+    let list_box = list_box.clone();
+    let status_label = status_label.clone();
+    receiver.attach(None, move |result| {
+        while let Some(row) = list_box.first_child() {
+            list_box.remove(&row);
+        }
+
+        match result {
+            Ok(folders) => {
+                for folder in folders.iter() {
+                    list_box.append(&folder_row(folder, &list_box, &status_label));
+                }
+            }
+            Err(err) => {
+                status_label.set_label(format!("Can not load folders: {}", err).as_str());
+            }
+        }
+
+        Continue(false)
+    });
+}
+
+fn folder_row(
+    folder: &FolderWrapper,
+    list_box: &gtk::ListBox,
+    status_label: &gtk::Label,
+) -> gtk::Box {
+    let enabled_check = gtk::CheckButton::builder()
+        .active(folder.enabled)
+        .tooltip_text("Scan this folder")
+        .build();
+
+    let last_scan = match folder.last_scan {
+        Some(last_scan) => format!("scanned {}", format_timestamp(last_scan)),
+        None => "never scanned".to_string(),
+    };
+    let label = gtk::Label::builder()
+        .label(
+            format!(
+                "{}  ({} images, {})",
+                folder.path.display(),
+                folder.images_count,
+                last_scan
+            )
+            .as_str(),
+        )
+        .halign(gtk::Align::Start)
+        .hexpand(true)
+        .build();
+
+    // patterns are separated by spaces, saved on enter
+    let excludes_entry = gtk::Entry::builder()
+        .text(folder.excludes.join(" ").as_str())
+        .placeholder_text("Excluded patterns")
+        .tooltip_text("Gitignore-style patterns separated by spaces, for example: raw/ *.tmp")
+        .build();
+
+    let remove_btn = gtk::Button::builder()
+        .label("Remove")
+        .tooltip_text("Forget folder and its images, files are not deleted")
+        .build();
+
+    let folder_id = folder.id;
+    {
+        let status_label = status_label.clone();
+        enabled_check.connect_toggled(move |check| {
+            let enabled = check.is_active();
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(set_folder_enabled(folder_id, enabled))
+                    .expect("Can not send result to main thread");
+            });
+
+            let status_label = status_label.clone();
+            receiver.attach(None, move |result| {
+                if let Err(err) = result {
+                    status_label.set_label(format!("Can not update folder: {}", err).as_str());
+                }
+                Continue(false)
+            });
+        });
+    }
+
+    {
+        let status_label = status_label.clone();
+        excludes_entry.connect_activate(move |entry| {
+            let excludes: Vec<String> = entry
+                .text()
+                .split_whitespace()
+                .map(|x| x.to_string())
+                .collect();
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(set_folder_excludes(folder_id, excludes))
+                    .expect("Can not send result to main thread");
+            });
+
+            let status_label = status_label.clone();
+            receiver.attach(None, move |result| {
+                match result {
+                    Ok(()) => status_label.set_label("Exclusion patterns saved"),
+                    Err(err) => {
+                        status_label.set_label(format!("Can not update folder: {}", err).as_str())
+                    }
+                }
+                Continue(false)
+            });
+        });
+    }
+
+    {
+        let list_box = list_box.clone();
+        let status_label = status_label.clone();
+        let path = folder.path.clone();
+        remove_btn.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(remove_folder(folder_id))
+                    .expect("Can not send result to main thread");
+            });
+
+            let list_box = list_box.clone();
+            let status_label = status_label.clone();
+            let path = path.clone();
+            receiver.attach(None, move |result| {
+                match result {
+                    Ok(()) => {
+                        status_label.set_label(format!("Removed {}", path.display()).as_str())
+                    }
+                    Err(err) => {
+                        status_label.set_label(format!("Can not remove folder: {}", err).as_str())
+                    }
+                }
+                refresh_folders(&list_box, &status_label);
+                Continue(false)
+            });
+        });
+    }
+
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    row.append(&enabled_check);
+    row.append(&label);
+    row.append(&excludes_entry);
+    row.append(&remove_btn);
+
+    row
+}
@@ -1,2 +1,5 @@
+mod error_list;
+mod folder_list;
+mod policy_window;
 mod processes;
 pub mod window;
@@ -0,0 +1,404 @@
+use super::processes::apply_resolution_plan;
+use super::processes::build_resolution_plan;
+use super::processes::load_last_plan;
+use super::window::new_strategy_dropdown;
+use super::window::selected_strategy;
+use deduplicator::index::PlanActionWrapper;
+use deduplicator::resolver::plan::write_plan_csv;
+use deduplicator::resolver::plan::ActionStatus;
+use deduplicator::resolver::policy::Policy;
+use deduplicator::resolver::policy::PolicyRule;
+use gtk;
+use gtk::glib;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use std::thread;
+
+const RULE_LABELS: [&str; 6] = [
+    "-",
+    "Highest resolution",
+    "Largest file",
+    "Oldest file",
+    "Inside preferred folder",
+    "Name is not a copy",
+];
+const DEFAULT_RULES: [u32; 5] = [1, 2, 3, 5, 0];
+
+// id and actions of the shown plan
+type ShownPlan = Option<(i64, Vec<PlanActionWrapper>)>;
+
+pub struct PolicyWindow {
+    pub window: gtk::Window,
+    rule_dropdowns: Rc<Vec<gtk::DropDown>>,
+    preferred_folder_entry: gtk::Entry,
+    strategy_dropdown: gtk::DropDown,
+    preview_btn: gtk::Button,
+    apply_btn: gtk::Button,
+    export_btn: gtk::Button,
+    export_chooser: gtk::FileChooserDialog,
+    plan_view: gtk::TextView,
+    status_label: gtk::Label,
+    plan: Rc<RefCell<ShownPlan>>,
+}
+
+impl PolicyWindow {
+    pub fn new() -> Self {
+        let window = gtk::Window::builder()
+            .title("Automatic resolution")
+            .modal(true)
+            .hide_on_close(true)
+            .default_width(900)
+            .default_height(600)
+            .build();
+
+        let rule_dropdowns: Vec<gtk::DropDown> = DEFAULT_RULES
+            .iter()
+            .map(|x| {
+                let dropdown = gtk::DropDown::from_strings(&RULE_LABELS);
+                dropdown.set_selected(*x);
+                dropdown
+            })
+            .collect();
+
+        let preferred_folder_entry = gtk::Entry::builder()
+            .placeholder_text("Preferred folder")
+            .build();
+        let strategy_dropdown = new_strategy_dropdown();
+        let preview_btn = gtk::Button::builder().label("Preview").build();
+        let apply_btn = gtk::Button::builder()
+            .label("Apply")
+            .sensitive(false)
+            .build();
+        let export_btn = gtk::Button::builder()
+            .label("Export")
+            .sensitive(false)
+            .build();
+
+        let export_chooser = gtk::FileChooserDialog::builder()
+            .title("Export plan")
+            .action(gtk::FileChooserAction::Save)
+            .transient_for(&window)
+            .build();
+        export_chooser.add_button("Export", gtk::ResponseType::Accept);
+        export_chooser.add_button("Cancel", gtk::ResponseType::Cancel);
+        export_chooser.set_current_name("plan.csv");
+
+        let plan_view = gtk::TextView::builder()
+            .editable(false)
+            .monospace(true)
+            .build();
+        let status_label = gtk::Label::builder().label("").build();
+
+        let main_grid = gtk::Box::new(gtk::Orientation::Vertical, 10);
+
+        let rules_grid = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        rules_grid.append(&gtk::Label::new(Some("Keep image with:")));
+        for dropdown in rule_dropdowns.iter() {
+            rules_grid.append(dropdown);
+        }
+        main_grid.append(&rules_grid);
+        main_grid.append(&preferred_folder_entry);
+
+        let plan_scroll = gtk::ScrolledWindow::builder()
+            .child(&plan_view)
+            .vexpand(true)
+            .build();
+        main_grid.append(&plan_scroll);
+        main_grid.append(&status_label);
+
+        let buttons_grid = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        buttons_grid.set_halign(gtk::Align::Center);
+        buttons_grid.append(&strategy_dropdown);
+        buttons_grid.append(&preview_btn);
+        buttons_grid.append(&apply_btn);
+        buttons_grid.append(&export_btn);
+        main_grid.append(&buttons_grid);
+
+        window.set_child(Some(&main_grid));
+
+        let result = Self {
+            window,
+            rule_dropdowns: Rc::new(rule_dropdowns),
+            preferred_folder_entry,
+            strategy_dropdown,
+            preview_btn,
+            apply_btn,
+            export_btn,
+            export_chooser,
+            plan_view,
+            status_label,
+            plan: Rc::new(RefCell::new(None)),
+        };
+
+        result.attach_handlers();
+
+        result
+    }
+
+    fn attach_handlers(&self) {
+        self.handle_show();
+        self.handle_preview_btn();
+        self.handle_apply_btn();
+        self.handle_export();
+    }
+
+    // last not applied plan can be reviewed and applied later
+    fn handle_show(&self) {
+        let plan_view = self.plan_view.clone();
+        let status_label = self.status_label.clone();
+        let buttons = self.get_plan_buttons();
+        let plan = self.plan.clone();
+
+        self.window.connect_show(move |_| {
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(load_last_plan())
+                    .expect("Can not send plan to main thread");
+            });
+
+            let plan_view = plan_view.clone();
+            let status_label = status_label.clone();
+            let buttons = buttons.clone();
+            let plan = plan.clone();
+            receiver.attach(None, move |result| {
+                match result {
+                    Ok(Some(loaded_plan)) => {
+                        show_plan(loaded_plan, &plan, &plan_view, &status_label, &buttons);
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        status_label.set_label(format!("Can not load plan: {}", err).as_str());
+                    }
+                }
+
+                Continue(false)
+            });
+        });
+    }
+
+    fn handle_preview_btn(&self) {
+        let rule_dropdowns = self.rule_dropdowns.clone();
+        let preferred_folder_entry = self.preferred_folder_entry.clone();
+        let strategy_dropdown = self.strategy_dropdown.clone();
+        let preview_btn = self.preview_btn.clone();
+        let plan_view = self.plan_view.clone();
+        let status_label = self.status_label.clone();
+        let buttons = self.get_plan_buttons();
+        let plan = self.plan.clone();
+
+        self.preview_btn.connect_clicked(move |_| {
+            let policy = Policy {
+                rules: selected_rules(&rule_dropdowns, &preferred_folder_entry),
+                strategy: selected_strategy(&strategy_dropdown),
+            };
+
+            preview_btn.set_sensitive(false);
+            buttons.iter().for_each(|x| x.set_sensitive(false));
+            status_label.set_label("Building plan");
+
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(build_resolution_plan(policy))
+                    .expect("Can not send plan to main thread");
+            });
+
+            let preview_btn = preview_btn.clone();
+            let plan_view = plan_view.clone();
+            let status_label = status_label.clone();
+            let buttons = buttons.clone();
+            let plan = plan.clone();
+            receiver.attach(None, move |result| {
+                preview_btn.set_sensitive(true);
+
+                match result {
+                    Ok(new_plan) => {
+                        show_plan(new_plan, &plan, &plan_view, &status_label, &buttons);
+                    }
+                    Err(err) => {
+                        status_label.set_label(format!("Can not build plan: {}", err).as_str());
+                    }
+                }
+
+                Continue(false)
+            });
+        });
+    }
+
+    fn handle_apply_btn(&self) {
+        let preview_btn = self.preview_btn.clone();
+        let plan_view = self.plan_view.clone();
+        let status_label = self.status_label.clone();
+        let buttons = self.get_plan_buttons();
+        let plan = self.plan.clone();
+
+        self.apply_btn.connect_clicked(move |_| {
+            let plan_id = match plan.borrow().as_ref() {
+                Some((plan_id, _)) => *plan_id,
+                None => return,
+            };
+
+            preview_btn.set_sensitive(false);
+            buttons.iter().for_each(|x| x.set_sensitive(false));
+            status_label.set_label("Applying plan");
+
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                sender
+                    .send(apply_resolution_plan(plan_id))
+                    .expect("Can not send result to main thread");
+            });
+
+            let preview_btn = preview_btn.clone();
+            let plan_view = plan_view.clone();
+            let status_label = status_label.clone();
+            let buttons = buttons.clone();
+            let plan = plan.clone();
+            receiver.attach(None, move |result| {
+                preview_btn.set_sensitive(true);
+
+                match result {
+                    Ok(actions) => {
+                        show_plan(
+                            (plan_id, actions),
+                            &plan,
+                            &plan_view,
+                            &status_label,
+                            &buttons,
+                        );
+                        // applied plan can only be exported
+                        buttons[0].set_sensitive(false);
+                    }
+                    Err(err) => {
+                        status_label.set_label(format!("Can not apply plan: {}", err).as_str());
+                    }
+                }
+
+                Continue(false)
+            });
+        });
+    }
+
+    fn handle_export(&self) {
+        let export_chooser = self.export_chooser.clone();
+        self.export_btn.connect_clicked(move |_| {
+            export_chooser.show();
+        });
+
+        let status_label = self.status_label.clone();
+        let plan = self.plan.clone();
+        self.export_chooser
+            .connect_response(move |dialog, response| {
+                dialog.hide();
+
+                if response != gtk::ResponseType::Accept {
+                    return;
+                }
+
+                let path = match dialog.file().and_then(|x| x.path()) {
+                    Some(path) => path,
+                    None => return,
+                };
+
+                let result = match plan.borrow().as_ref() {
+                    Some((_, actions)) => fs::File::create(&path)
+                        .and_then(|mut file| write_plan_csv(&mut file, actions)),
+                    None => return,
+                };
+
+                match result {
+                    Ok(()) => {
+                        status_label.set_label(format!("Exported to {}", path.display()).as_str())
+                    }
+                    Err(err) => {
+                        status_label.set_label(format!("Can not export plan: {}", err).as_str())
+                    }
+                }
+            });
+    }
+
+    // apply and export buttons, in this order
+    fn get_plan_buttons(&self) -> Rc<Vec<gtk::Button>> {
+        Rc::new(vec![self.apply_btn.clone(), self.export_btn.clone()])
+    }
+}
+
+fn show_plan(
+    new_plan: (i64, Vec<PlanActionWrapper>),
+    plan: &RefCell<ShownPlan>,
+    plan_view: &gtk::TextView,
+    status_label: &gtk::Label,
+    buttons: &[gtk::Button],
+) {
+    let (plan_id, actions) = &new_plan;
+    let pending = actions
+        .iter()
+        .filter(|x| x.status == ActionStatus::Pending.name())
+        .count();
+
+    plan_view.buffer().set_text(&describe_plan(actions));
+    status_label.set_label(
+        format!(
+            "Plan #{}: {} actions, {} pending",
+            plan_id,
+            actions.len(),
+            pending
+        )
+        .as_str(),
+    );
+    buttons[0].set_sensitive(pending > 0);
+    buttons[1].set_sensitive(!actions.is_empty());
+
+    plan.replace(Some(new_plan));
+}
+
+fn selected_rules(
+    rule_dropdowns: &[gtk::DropDown],
+    preferred_folder_entry: &gtk::Entry,
+) -> Vec<PolicyRule> {
+    let preferred_folder = preferred_folder_entry.text().to_string();
+
+    rule_dropdowns
+        .iter()
+        .filter_map(|x| match x.selected() {
+            1 => Some(PolicyRule::HighestResolution),
+            2 => Some(PolicyRule::LargestFile),
+            3 => Some(PolicyRule::OldestModified),
+            4 if !preferred_folder.is_empty() => {
+                Some(PolicyRule::PreferredFolder(preferred_folder.clone()))
+            }
+            5 => Some(PolicyRule::AvoidCopyName),
+            _ => None,
+        })
+        .collect()
+}
+
+fn describe_plan(actions: &[PlanActionWrapper]) -> String {
+    let mut result = String::new();
+
+    for action in actions {
+        result.push_str(
+            format!(
+                "[{}] {} {}\n    keep {} ({})\n",
+                action.status,
+                action.strategy,
+                action.remove_path.display(),
+                action.keep_path.display(),
+                action.reason
+            )
+            .as_str(),
+        );
+
+        if let Some(error) = &action.error {
+            result.push_str(format!("    {}\n", error).as_str());
+        }
+    }
+
+    result
+}
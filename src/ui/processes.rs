@@ -1,206 +1,338 @@
-use crate::database::AcquiredConnection;
-use crate::database::Database;
-use crate::database::FolderWrapper;
-use crate::database::ImageWrapper;
-use crate::filesystem::find_file_recursive;
+use deduplicator::archive;
+use deduplicator::config;
+use deduplicator::index::Database;
+use deduplicator::index::FailedImageWrapper;
+use deduplicator::index::FolderWrapper;
+use deduplicator::index::PlanActionWrapper;
+use deduplicator::index::ScanSessionWrapper;
+use deduplicator::report::build_report;
+use deduplicator::report::write_report;
+use deduplicator::report::ReportFormat;
+use deduplicator::resolver;
+use deduplicator::resolver::plan;
+use deduplicator::resolver::policy::Policy;
+use deduplicator::resolver::ResolutionStrategy;
+use deduplicator::scanner;
+use deduplicator::scanner::ScanControl;
+use deduplicator::scanner::ScanFolderStatus;
+use deduplicator::scanner::ScanOptions;
+use deduplicator::thumbnail;
+use deduplicator::thumbnail::ThumbnailSize;
+use deduplicator::watcher;
+use deduplicator::watcher::WatchStatus;
+use gtk::glib;
 use gtk::glib::Sender;
-use image;
+use gtk::prelude::*;
+use std::cell::RefCell;
 use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread;
 use tokio;
 
-const HASH_WORKERS: usize = 8;
+// library chosen in UI, `None` until user switches it
+static LIBRARY: Mutex<Option<String>> = Mutex::new(None);
 
-pub enum ScanFolderStatus {
-    ScanningFolders(String),
-    ImageFound(String),
-    HashCalculated(String),
-    Done,
+// previews are shown at this size, bigger than the window needs for most screens
+const PREVIEW_SIZE: ThumbnailSize = ThumbnailSize::XLarge;
+
+type Preview = Result<image::RgbImage, String>;
+
+thread_local! {
+    // requests of the main thread are answered by one worker in turn
+    static PREVIEWS: mpsc::Sender<(PathBuf, Sender<Preview>)> = start_preview_worker();
+}
+
+pub fn current_library() -> String {
+    LIBRARY
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(config::current_library)
 }
 
-pub enum HashingStatus {
-    NewHash(ImageWrapper),
-    Done,
+pub fn set_library(library: String) {
+    *LIBRARY.lock().unwrap() = Some(library);
 }
 
+// creates database of the current library if it does not exist yet
 #[tokio::main]
-pub async fn insert_new_folders(paths: Vec<String>) -> Vec<FolderWrapper> {
-    let connection = Database::connect_default().await;
-    let mut connection_pool = connection.get_connection().await;
-    connection_pool.insert_folders(paths).await
+pub async fn prepare_library() {
+    open_database().await.migrate().await;
+}
+
+async fn open_database() -> Database {
+    Database::open(&config::library_database_path(&current_library())).await
 }
 
 #[tokio::main]
-pub async fn scan_folders(sender: Sender<ScanFolderStatus>) {
-    let extensions = vec![".jpg".to_string(), ".jpeg".to_string(), ".png".to_string()];
-    let connection = Database::connect_default().await;
+pub async fn insert_new_folders(paths: Vec<PathBuf>) -> Vec<FolderWrapper> {
+    let connection = open_database().await;
     let mut connection_pool = connection.get_connection().await;
+    connection_pool.insert_folders(paths).await
+}
 
-    let folders = connection_pool.get_all_folders().await.unwrap();
-
-    for folder in folders.iter() {
-        sender
-            .send(ScanFolderStatus::ScanningFolders(folder.path.clone()))
-            .unwrap();
-
-        let images = find_file_recursive(folder.path.clone(), &extensions);
-
-        for image in images.iter() {
-            let result = connection_pool.insert_image(image).await;
-            match result {
-                Ok(()) => {
-                    sender
-                        .send(ScanFolderStatus::ImageFound(image.clone()))
-                        .unwrap();
-                }
-                Err(_) => {}
-            };
-        }
-    }
+#[tokio::main]
+pub async fn get_folders() -> Result<Vec<FolderWrapper>, sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    start_hashing(connection_pool, &sender).await;
-    sender.send(ScanFolderStatus::Done).unwrap();
+    connection.get_all_folders().await
 }
 
-async fn start_hashing(mut connection_pool: AcquiredConnection, sender: &Sender<ScanFolderStatus>) {
-    let images = connection_pool.get_non_hashed_images().await.unwrap();
-    let mut images = split_images_for_processing(images, HASH_WORKERS);
-    let mut receivers = Vec::new();
+#[tokio::main]
+pub async fn set_folder_enabled(id: i64, enabled: bool) -> Result<(), sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    for _ in 0..HASH_WORKERS {
-        let images_chunk = images.pop().unwrap();
-        let (sender, receiver) = mpsc::channel();
+    connection.set_folder_enabled(id, enabled).await
+}
 
-        receivers.push(receiver);
-        thread::spawn(move || {
-            do_hashing(images_chunk, sender);
-        });
-    }
+#[tokio::main]
+pub async fn set_folder_excludes(id: i64, excludes: Vec<String>) -> Result<(), sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    let mut done_count = 0;
-
-    while done_count < receivers.len() - 1 {
-        for receiver in receivers.iter() {
-            let message = match receiver.recv() {
-                Ok(val) => val,
-                _ => {
-                    continue;
-                }
-            };
-
-            match message {
-                HashingStatus::NewHash(image) => {
-                    connection_pool
-                        .update_image_hash(image.id, image.hash)
-                        .await
-                        .unwrap();
-                    sender
-                        .send(ScanFolderStatus::HashCalculated(image.path.clone()))
-                        .unwrap();
-                }
-                HashingStatus::Done => {
-                    done_count += 1;
-                }
-            }
-        }
-    }
+    connection.set_folder_excludes(id, &excludes).await
 }
 
-fn do_hashing(images: Vec<ImageWrapper>, sender: mpsc::Sender<HashingStatus>) {
-    for image in images {
-        let hash_result = get_image_hash(&image.path);
-        let mut image_to_send = image.clone();
-        image_to_send.hash = hash_result;
-        sender.send(HashingStatus::NewHash(image_to_send)).unwrap();
-    }
+#[tokio::main]
+pub async fn remove_folder(id: i64) -> Result<(), sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    sender.send(HashingStatus::Done).unwrap();
+    connection.remove_folder(id).await
 }
 
-fn get_image_hash(path: &String) -> Option<i64> {
-    let img = image::open(path);
+#[tokio::main]
+pub async fn last_scan_session() -> Result<Option<ScanSessionWrapper>, sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    return match img {
-        Ok(img) => Some(calc_hash(img)),
-        Err(_) => None,
-    };
+    connection.get_last_scan_session().await
 }
 
-fn calc_hash(img: image::DynamicImage) -> i64 {
-    let img = img.resize_exact(8, 8, image::imageops::FilterType::Nearest);
-    let img = img.grayscale();
-
-    let mut light_medium: u64 = 0;
-    for pixel in img.as_bytes() {
-        light_medium += *pixel as u64;
-    }
+#[tokio::main]
+pub async fn get_failed_images() -> Result<Vec<FailedImageWrapper>, sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    light_medium = light_medium / 64;
+    connection.get_failed_images().await
+}
 
-    let mut hash: i64 = 0;
-    let mut marker: i64 = 1;
-    for pixel in img.as_bytes() {
-        if *pixel as u64 > light_medium {
-            hash |= marker;
-        }
-        marker = marker << 1;
-    }
+#[tokio::main]
+pub async fn scan_folders(sender: Sender<ScanFolderStatus>, control: ScanControl) {
+    let connection = open_database().await;
+    let mut connection_pool = connection.get_connection().await;
 
-    hash
+    scanner::scan_folders(
+        &mut connection_pool,
+        &ScanOptions::load(),
+        &control,
+        |status| sender.send(status).unwrap(),
+    )
+    .await;
 }
 
-fn split_images_for_processing(
-    mut images: Vec<ImageWrapper>,
-    size: usize,
-) -> Vec<Vec<ImageWrapper>> {
-    let mut images_chunks = Vec::new();
+// runs until `stop` is set, an error ends the watch
+#[tokio::main]
+pub async fn watch_folders(sender: Sender<io::Result<WatchStatus>>, stop: Arc<AtomicBool>) {
+    let connection = open_database().await;
+    let mut connection_pool = connection.get_connection().await;
 
-    for _ in 0..size {
-        images_chunks.push(Vec::new());
-    }
+    let result = watcher::watch_folders(
+        &mut connection_pool,
+        &ScanOptions::load(),
+        &stop,
+        |status| sender.send(Ok(status)).unwrap(),
+    )
+    .await;
 
-    for i in 0..images.len() {
-        let image = images.pop().unwrap();
-        images_chunks[i % size].push(image);
+    if let Err(err) = result {
+        sender.send(Err(err)).unwrap();
     }
-
-    images_chunks
 }
 
+// paths of the shown images are kept in `shown_pair`, they may be not valid UTF-8
 pub async fn find_duplicates(
     left_img: gtk::Image,
     left_img_label: gtk::Label,
     right_img: gtk::Image,
     right_img_label: gtk::Label,
+    shown_pair: &Rc<RefCell<Option<(PathBuf, PathBuf)>>>,
+    remove_buttons: &(gtk::Button, gtk::Button),
 ) -> Result<(), sqlx::Error> {
-    let database = Database::connect_default().await;
+    let database = open_database().await;
     let mut connection = database.get_connection().await;
 
     let images = connection.get_duplicates().await?;
+    shown_pair.replace(images.map(|x| (x.0.path, x.1.path)));
+
+    show_image(
+        &left_img,
+        &left_img_label,
+        &remove_buttons.0,
+        shown_pair,
+        |x| &x.0,
+    );
+    show_image(
+        &right_img,
+        &right_img_label,
+        &remove_buttons.1,
+        shown_pair,
+        |x| &x.1,
+    );
+
+    Ok(())
+}
+
+// previews come from the thumbnail cache on the preview worker, so big images do not block
+// the window. images inside archives are read-only, their remove button is disabled
+fn show_image(
+    image: &gtk::Image,
+    label: &gtk::Label,
+    remove_btn: &gtk::Button,
+    shown_pair: &Rc<RefCell<Option<(PathBuf, PathBuf)>>>,
+    side: fn(&(PathBuf, PathBuf)) -> &PathBuf,
+) {
+    image.set_paintable(None::<&gtk::gdk::Paintable>);
+
+    let path = match shown_pair.borrow().as_ref() {
+        Some(pair) => side(pair).clone(),
+        None => {
+            label.set_label("");
+            remove_btn.set_sensitive(true);
+            return;
+        }
+    };
 
-    if let Some(images) = images {
-        left_img.set_file(Some(images.0.path.as_str()));
-        right_img.set_file(Some(images.1.path.as_str()));
-        left_img_label.set_label(&images.0.path);
-        right_img_label.set_label(&images.1.path);
+    if archive::is_virtual_path(&path) {
+        label
+            .set_label(format!("{}  (inside archive, read-only)", path.to_string_lossy()).as_str());
+        remove_btn.set_sensitive(false);
     } else {
-        left_img.set_file(None);
-        right_img.set_file(None);
-        left_img_label.set_label("");
-        right_img_label.set_label("");
+        label.set_label(&path.to_string_lossy());
+        remove_btn.set_sensitive(true);
     }
 
-    Ok(())
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    PREVIEWS.with(|previews| {
+        previews
+            .send((path.clone(), sender))
+            .expect("Can not send preview request")
+    });
+
+    let image = image.clone();
+    let label = label.clone();
+    let shown_pair = shown_pair.clone();
+    receiver.attach(None, move |thumbnail| {
+        // another pair may be shown already
+        if shown_pair.borrow().as_ref().map(side) != Some(&path) {
+            return Continue(false);
+        }
+
+        match thumbnail {
+            Ok(thumbnail) => {
+                let (width, height) = thumbnail.dimensions();
+                let texture = gtk::gdk::MemoryTexture::new(
+                    width as i32,
+                    height as i32,
+                    gtk::gdk::MemoryFormat::R8g8b8,
+                    &glib::Bytes::from_owned(thumbnail.into_raw()),
+                    width as usize * 3,
+                );
+                image.set_paintable(Some(&texture));
+            }
+            Err(err) => {
+                label.set_label(format!("{}  (can not be shown: {})", label.label(), err).as_str());
+            }
+        }
+
+        Continue(false)
+    });
 }
 
-pub async fn remove_and_protect_image(image_to_protect: &String, image_to_remove: Option<&String>) {
-    if let Some(image_to_remove) = image_to_remove {
-        fs::remove_file(image_to_remove).unwrap();
-    }
+// previews of pairs which are not shown anymore are still made, the receiver drops them
+fn start_preview_worker() -> mpsc::Sender<(PathBuf, Sender<Preview>)> {
+    let (sender, receiver) = mpsc::channel::<(PathBuf, Sender<Preview>)>();
+
+    thread::spawn(move || {
+        for (path, reply) in receiver {
+            let preview = thumbnail::load_thumbnail(&config::thumbnails_dir(), &path, PREVIEW_SIZE)
+                .map(|x| x.to_rgb8())
+                .map_err(|err| err.to_string());
+            // the window may be closed already
+            let _ = reply.send(preview);
+        }
+    });
+
+    sender
+}
+
+pub async fn remove_and_protect_image(
+    image_to_protect: &Path,
+    image_to_remove: Option<&Path>,
+    strategy: ResolutionStrategy,
+) -> io::Result<()> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
 
-    let database = Database::connect_default().await;
+    resolver::remove_and_protect_image(
+        &mut connection,
+        image_to_protect,
+        image_to_remove,
+        strategy,
+        false,
+    )
+    .await
+}
+
+#[tokio::main]
+pub async fn build_resolution_plan(
+    policy: Policy,
+) -> Result<(i64, Vec<PlanActionWrapper>), sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
+
+    plan::build_plan(&mut connection, &policy).await
+}
+
+#[tokio::main]
+pub async fn load_last_plan() -> Result<Option<(i64, Vec<PlanActionWrapper>)>, sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
+
+    plan::load_last_plan(&mut connection).await
+}
+
+#[tokio::main]
+pub async fn apply_resolution_plan(plan_id: i64) -> Result<Vec<PlanActionWrapper>, sqlx::Error> {
+    let database = open_database().await;
+    let mut connection = database.get_connection().await;
+
+    plan::apply_plan(&mut connection, plan_id).await
+}
+
+// returns number of written duplicate groups
+#[tokio::main]
+pub async fn export_report(path: PathBuf) -> Result<usize, String> {
+    let database = open_database().await;
     let mut connection = database.get_connection().await;
 
-    connection.mark_protected(&image_to_protect).await.unwrap();
+    let groups = connection
+        .get_duplicate_groups()
+        .await
+        .map_err(|x| x.to_string())?;
+    let report = build_report(groups);
+
+    let mut file = fs::File::create(&path).map_err(|x| x.to_string())?;
+    write_report(&mut file, &report, ReportFormat::from_path(&path)).map_err(|x| x.to_string())?;
+
+    Ok(report.len())
 }
@@ -1,14 +1,33 @@
+use super::error_list::ErrorList;
+use super::folder_list::FolderList;
+use super::policy_window::PolicyWindow;
+use super::processes::current_library;
+use super::processes::export_report;
 use super::processes::find_duplicates;
 use super::processes::insert_new_folders;
+use super::processes::last_scan_session;
+use super::processes::prepare_library;
 use super::processes::remove_and_protect_image;
 use super::processes::scan_folders;
-use super::processes::ScanFolderStatus;
+use super::processes::set_library;
+use super::processes::watch_folders;
+use deduplicator::config;
+use deduplicator::resolver::ResolutionStrategy;
+use deduplicator::scanner::describe_session;
+use deduplicator::scanner::ScanControl;
+use deduplicator::scanner::ScanFolderStatus;
+use deduplicator::watcher::WatchStatus;
 use futures::executor;
 use gtk;
 use gtk::glib;
 use gtk::prelude::*;
 use gtk::Application;
+use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::thread;
 
 pub struct MainWindow {
@@ -16,13 +35,32 @@ pub struct MainWindow {
     right_image: gtk::Image,
     left_image_label: gtk::Label,
     right_image_label: gtk::Label,
+    // paths of the shown images, left and right
+    shown_pair: Rc<RefCell<Option<(PathBuf, PathBuf)>>>,
     remove_left_btn: gtk::Button,
     remove_right_btn: gtk::Button,
     not_duplicates_btn: gtk::Button,
+    strategy_dropdown: gtk::DropDown,
     add_folder_btn: gtk::Button,
     scan_btn: gtk::Button,
+    pause_scan_btn: gtk::ToggleButton,
+    cancel_scan_btn: gtk::Button,
+    // control of the running scan
+    scan_control: Rc<RefCell<Option<ScanControl>>>,
+    watch_btn: gtk::ToggleButton,
+    auto_resolve_btn: gtk::Button,
+    export_report_btn: gtk::Button,
+    report_chooser: gtk::FileChooserDialog,
+    policy_window: PolicyWindow,
     new_folder_chooser: gtk::FileChooserDialog,
+    folder_list: FolderList,
+    error_list: ErrorList,
+    library_list: gtk::StringList,
+    library_dropdown: gtk::DropDown,
+    new_library_entry: gtk::Entry,
+    create_library_btn: gtk::Button,
     status_label: gtk::Label,
+    scan_progress: gtk::ProgressBar,
 }
 
 impl MainWindow {
@@ -42,6 +80,8 @@ impl MainWindow {
         let remove_right_btn = gtk::Button::new();
         remove_right_btn.set_label("Remove right");
 
+        let strategy_dropdown = new_strategy_dropdown();
+
         let add_folder_btn = gtk::Button::builder().label("Add folder").build();
 
         let new_folder_chooser = gtk::FileChooserDialog::builder()
@@ -53,7 +93,56 @@ impl MainWindow {
             .build();
 
         let scan_btn = gtk::Button::builder().label("Scan").build();
+        let pause_scan_btn = gtk::ToggleButton::builder()
+            .label("Pause")
+            .sensitive(false)
+            .build();
+        let cancel_scan_btn = gtk::Button::builder()
+            .label("Cancel")
+            .tooltip_text("Stop the scan, found images and hashes are kept for the next one")
+            .sensitive(false)
+            .build();
+        let watch_btn = gtk::ToggleButton::builder()
+            .label("Watch folders")
+            .tooltip_text("Add changed images and notify about new duplicates while this is on")
+            .build();
+        let auto_resolve_btn = gtk::Button::builder().label("Auto resolve").build();
+        let export_report_btn = gtk::Button::builder().label("Export report").build();
+
+        let report_chooser = gtk::FileChooserDialog::builder()
+            .title("Export report")
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        report_chooser.add_button("Export", gtk::ResponseType::Accept);
+        report_chooser.add_button("Cancel", gtk::ResponseType::Cancel);
+        report_chooser.set_current_name("report.json");
+
+        let mut libraries = config::list_libraries();
+        let library = current_library();
+        // the configured library is listed even before its database exists
+        let selected = match libraries.iter().position(|x| *x == library) {
+            Some(index) => index,
+            None => {
+                libraries.push(library.clone());
+                libraries.len() - 1
+            }
+        };
+        let library_names: Vec<&str> = libraries.iter().map(|x| x.as_str()).collect();
+        let library_list = gtk::StringList::new(&library_names);
+        let library_dropdown =
+            gtk::DropDown::new(Some(library_list.clone()), gtk::Expression::NONE);
+        library_dropdown.set_selected(selected as u32);
+        let new_library_entry = gtk::Entry::builder()
+            .placeholder_text("New library")
+            .build();
+        let create_library_btn = gtk::Button::builder().label("Create library").build();
+
         let status_label = gtk::Label::builder().label("").build();
+        status_label.set_label(&last_scan_description());
+        let scan_progress = gtk::ProgressBar::builder()
+            .show_text(true)
+            .visible(false)
+            .build();
 
         new_folder_chooser.add_button("Add", gtk::ResponseType::Accept);
         new_folder_chooser.add_button("Cancel", gtk::ResponseType::Cancel);
@@ -71,12 +160,29 @@ impl MainWindow {
             remove_left_btn,
             remove_right_btn,
             not_duplicates_btn,
+            strategy_dropdown,
             add_folder_btn,
             new_folder_chooser,
             scan_btn,
+            pause_scan_btn,
+            cancel_scan_btn,
+            scan_control: Rc::new(RefCell::new(None)),
+            watch_btn,
+            auto_resolve_btn,
+            export_report_btn,
+            report_chooser,
+            library_list,
+            library_dropdown,
+            new_library_entry,
+            create_library_btn,
+            policy_window: PolicyWindow::new(),
+            folder_list: FolderList::new(status_label.clone()),
+            error_list: ErrorList::new(status_label.clone()),
             status_label,
+            scan_progress,
             left_image_label: gtk::Label::new(None),
             right_image_label: gtk::Label::new(None),
+            shown_pair: Rc::new(RefCell::new(None)),
         };
 
         result.attach_handlers();
@@ -87,18 +193,237 @@ impl MainWindow {
     fn attach_handlers(&self) {
         self.handle_add_folders_btn();
         self.handle_scan_btn();
+        self.handle_watch_btn();
         self.handle_remove_left();
         self.handle_remove_right();
         self.handle_save_both();
+        self.handle_auto_resolve_btn();
+        self.handle_export_report();
+        self.handle_library_dropdown();
+        self.handle_create_library_btn();
+    }
+
+    fn handle_library_dropdown(&self) {
+        let blockable_widgets = Rc::new(self.get_blockable_widgets());
+        let library_list = self.library_list.clone();
+        let folder_list = self.folder_list.clone();
+        let error_list = self.error_list.clone();
+        let status_label = self.status_label.clone();
+        let left_image = self.left_image.clone();
+        let right_image = self.right_image.clone();
+        let left_image_label = self.left_image_label.clone();
+        let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
+        let watch_btn = self.watch_btn.clone();
+
+        self.library_dropdown
+            .connect_selected_notify(move |dropdown| {
+                let library = match library_list.string(dropdown.selected()) {
+                    Some(library) => library.to_string(),
+                    None => return,
+                };
+
+                if library == current_library() {
+                    return;
+                }
+
+                // the watch follows folders of the previous library
+                watch_btn.set_active(false);
+                set_library(library.clone());
+                blockable_widgets
+                    .iter()
+                    .for_each(|x| x.set_sensitive(false));
+
+                let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+                thread::spawn(move || {
+                    prepare_library();
+                    sender
+                        .send(true)
+                        .expect("Can not send signal to main thread");
+                });
+
+                let blockable_widgets = blockable_widgets.clone();
+                let folder_list = folder_list.clone();
+                let error_list = error_list.clone();
+                let status_label = status_label.clone();
+                let left_image = left_image.clone();
+                let right_image = right_image.clone();
+                let left_image_label = left_image_label.clone();
+                let right_image_label = right_image_label.clone();
+                let shown_pair = shown_pair.clone();
+                let remove_buttons = remove_buttons.clone();
+                receiver.attach(None, move |x| {
+                    blockable_widgets.iter().for_each(|x| x.set_sensitive(true));
+                    status_label.set_label(
+                        format!("Library {}. {}", library, last_scan_description()).as_str(),
+                    );
+                    folder_list.refresh();
+                    error_list.refresh();
+                    executor::block_on(find_duplicates(
+                        left_image.clone(),
+                        left_image_label.clone(),
+                        right_image.clone(),
+                        right_image_label.clone(),
+                        &shown_pair,
+                        &remove_buttons,
+                    ))
+                    .unwrap();
+                    Continue(!x)
+                });
+            });
+    }
+
+    fn handle_create_library_btn(&self) {
+        let library_list = self.library_list.clone();
+        let library_dropdown = self.library_dropdown.clone();
+        let new_library_entry = self.new_library_entry.clone();
+        let status_label = self.status_label.clone();
+
+        self.create_library_btn.connect_clicked(move |_| {
+            let library = new_library_entry.text().to_string();
+
+            if !config::is_valid_library_name(&library) {
+                status_label
+                    .set_label("Library name can contain only letters, digits, '-' and '_'");
+                return;
+            }
+
+            let position = (0..library_list.n_items())
+                .find(|x| library_list.string(*x).map(|x| x.to_string()) == Some(library.clone()));
+            let position = match position {
+                Some(position) => position,
+                None => {
+                    library_list.append(&library);
+                    library_list.n_items() - 1
+                }
+            };
+
+            new_library_entry.set_text("");
+            // database of the new library is created by the dropdown handler
+            library_dropdown.set_selected(position);
+        });
+    }
+
+    fn handle_export_report(&self) {
+        let report_chooser = self.report_chooser.clone();
+        self.export_report_btn.connect_clicked(move |_| {
+            report_chooser.show();
+        });
+
+        let status_label = self.status_label.clone();
+        self.report_chooser
+            .connect_response(move |dialog, response| {
+                dialog.hide();
+
+                if response != gtk::ResponseType::Accept {
+                    return;
+                }
+
+                let path = match dialog.file().and_then(|x| x.path()) {
+                    Some(path) => path,
+                    None => return,
+                };
+
+                status_label.set_label("Exporting report");
+
+                let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+                let report_path = path.clone();
+                thread::spawn(move || {
+                    sender
+                        .send(export_report(report_path))
+                        .expect("Can not send signal to main thread");
+                });
+
+                let status_label = status_label.clone();
+                receiver.attach(None, move |result| {
+                    match result {
+                        Ok(groups) => status_label.set_label(
+                            format!("Exported {} groups to {}", groups, path.display()).as_str(),
+                        ),
+                        Err(err) => status_label
+                            .set_label(format!("Can not export report: {}", err).as_str()),
+                    }
+
+                    Continue(false)
+                });
+            });
+    }
+
+    fn handle_auto_resolve_btn(&self) {
+        let policy_window = self.policy_window.window.clone();
+        self.auto_resolve_btn.connect_clicked(move |_| {
+            policy_window.show();
+        });
+
+        let left_image = self.left_image.clone();
+        let right_image = self.right_image.clone();
+        let left_image_label = self.left_image_label.clone();
+        let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
+
+        // applied plan may have resolved the pair shown right now
+        self.policy_window.window.connect_hide(move |_| {
+            executor::block_on(find_duplicates(
+                left_image.clone(),
+                left_image_label.clone(),
+                right_image.clone(),
+                right_image_label.clone(),
+                &shown_pair,
+                &remove_buttons,
+            ))
+            .unwrap();
+        });
     }
 
     fn handle_scan_btn(&self) {
         let blockable_widgets = Rc::new(self.get_blockable_widgets());
+        let folder_list = self.folder_list.clone();
+        let error_list = self.error_list.clone();
         let status_label = self.status_label.clone();
         let left_image = self.left_image.clone();
         let right_image = self.right_image.clone();
         let left_image_label = self.left_image_label.clone();
         let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
+        let pause_scan_btn = self.pause_scan_btn.clone();
+        let cancel_scan_btn = self.cancel_scan_btn.clone();
+        let scan_control = self.scan_control.clone();
+        let scan_progress = self.scan_progress.clone();
+
+        {
+            let scan_control = scan_control.clone();
+            let status_label = status_label.clone();
+            self.pause_scan_btn.connect_toggled(move |button| {
+                if let Some(control) = scan_control.borrow().as_ref() {
+                    if button.is_active() {
+                        control.pause();
+                        status_label.set_label("Scan paused");
+                    } else {
+                        control.resume();
+                        status_label.set_label("Scan resumed");
+                    }
+                }
+            });
+        }
+
+        {
+            let scan_control = scan_control.clone();
+            let status_label = status_label.clone();
+            let pause_scan_btn = pause_scan_btn.clone();
+            self.cancel_scan_btn.connect_clicked(move |button| {
+                if let Some(control) = scan_control.borrow().as_ref() {
+                    control.cancel();
+                    status_label.set_label("Cancelling scan");
+                }
+                button.set_sensitive(false);
+                pause_scan_btn.set_sensitive(false);
+            });
+        }
 
         self.scan_btn.connect_clicked(move |_| {
             status_label.set_label("Start scanning");
@@ -106,40 +431,92 @@ impl MainWindow {
                 .iter()
                 .for_each(|x| x.set_sensitive(false));
 
+            let control = ScanControl::default();
+            scan_control.replace(Some(control.clone()));
+            pause_scan_btn.set_active(false);
+            pause_scan_btn.set_sensitive(true);
+            cancel_scan_btn.set_sensitive(true);
+            scan_progress.set_fraction(0.0);
+            scan_progress.set_text(Some("Starting"));
+            scan_progress.set_visible(true);
+
             let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
 
             thread::spawn(move || {
-                scan_folders(sender);
+                scan_folders(sender, control);
             });
 
             let blockable_widgets_clone = blockable_widgets.clone();
-
+            let folder_list_clone = folder_list.clone();
+            let error_list_clone = error_list.clone();
             let status_label_clone = status_label.clone();
             let left_image_clone = left_image.clone();
             let right_image_clone = right_image.clone();
             let left_image_label_clone = left_image_label.clone();
             let right_image_label_clone = right_image_label.clone();
+            let shown_pair_clone = shown_pair.clone();
+            let remove_buttons_clone = remove_buttons.clone();
+            let pause_scan_btn_clone = pause_scan_btn.clone();
+            let cancel_scan_btn_clone = cancel_scan_btn.clone();
+            let scan_control_clone = scan_control.clone();
+            let scan_progress_clone = scan_progress.clone();
             receiver.attach(None, move |message| match message {
-                ScanFolderStatus::Done => {
+                ScanFolderStatus::Done
+                | ScanFolderStatus::Cancelled
+                | ScanFolderStatus::Failed(_) => {
+                    // taken first, so unpressing the pause button does not resume anything
+                    scan_control_clone.replace(None);
+                    pause_scan_btn_clone.set_active(false);
+                    pause_scan_btn_clone.set_sensitive(false);
+                    cancel_scan_btn_clone.set_sensitive(false);
+                    scan_progress_clone.set_visible(false);
                     blockable_widgets_clone
                         .iter()
                         .for_each(|x| x.set_sensitive(true));
-                    status_label_clone.set_label("Scan complete");
+                    status_label_clone.set_label(
+                        match message {
+                            ScanFolderStatus::Done => "Scan complete".to_string(),
+                            ScanFolderStatus::Failed(err) => {
+                                format!("Scan stopped, the next scan continues from here: {}", err)
+                            }
+                            _ => "Scan cancelled, the next scan continues from here".to_string(),
+                        }
+                        .as_str(),
+                    );
+                    folder_list_clone.refresh();
+                    error_list_clone.refresh();
                     executor::block_on(find_duplicates(
                         left_image_clone.clone(),
                         left_image_label_clone.clone(),
                         right_image_clone.clone(),
                         right_image_label_clone.clone(),
+                        &shown_pair_clone,
+                        &remove_buttons_clone,
                     ))
                     .unwrap();
                     Continue(false)
                 }
-                ScanFolderStatus::ImageFound(image) => {
-                    status_label_clone.set_label(format!("{}", image).as_str());
+                ScanFolderStatus::Resumed(walked) => {
+                    status_label_clone.set_label(
+                        format!(
+                            "Continuing the previous scan, {} folders are already walked",
+                            walked
+                        )
+                        .as_str(),
+                    );
                     Continue(true)
                 }
-                ScanFolderStatus::HashCalculated(image) => {
-                    status_label_clone.set_label(format!("Hash calculated: {}", image).as_str());
+                ScanFolderStatus::ScanningFolders(folder) => {
+                    status_label_clone.set_label(format!("Scanning {}", folder.display()).as_str());
+                    Continue(true)
+                }
+                ScanFolderStatus::Progress(progress) => {
+                    // nothing to measure against until all folders are walked
+                    match progress.fraction() {
+                        Some(fraction) => scan_progress_clone.set_fraction(fraction),
+                        None => scan_progress_clone.pulse(),
+                    }
+                    scan_progress_clone.set_text(Some(progress.to_string().as_str()));
                     Continue(true)
                 }
                 _ => Continue(true),
@@ -147,8 +524,86 @@ impl MainWindow {
         });
     }
 
+    fn handle_watch_btn(&self) {
+        let status_label = self.status_label.clone();
+        let left_image = self.left_image.clone();
+        let right_image = self.right_image.clone();
+        let left_image_label = self.left_image_label.clone();
+        let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
+        // stop flag of the running watch
+        let stop: Rc<RefCell<Option<Arc<AtomicBool>>>> = Rc::new(RefCell::new(None));
+
+        self.watch_btn.connect_toggled(move |button| {
+            if !button.is_active() {
+                if let Some(stop) = stop.take() {
+                    stop.store(true, Ordering::Relaxed);
+                    status_label.set_label("Watch stopped");
+                }
+                return;
+            }
+
+            let watch_stop = Arc::new(AtomicBool::new(false));
+            stop.replace(Some(watch_stop.clone()));
+
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+            thread::spawn(move || {
+                watch_folders(sender, watch_stop);
+            });
+
+            let button = button.clone();
+            let status_label = status_label.clone();
+            let left_image = left_image.clone();
+            let right_image = right_image.clone();
+            let left_image_label = left_image_label.clone();
+            let right_image_label = right_image_label.clone();
+            let shown_pair = shown_pair.clone();
+            let remove_buttons = remove_buttons.clone();
+            receiver.attach(None, move |message| {
+                match message {
+                    Ok(WatchStatus::Watching(count)) => {
+                        status_label.set_label(format!("Watching {} folders", count).as_str())
+                    }
+                    Ok(WatchStatus::ImageChanged(image)) => {
+                        status_label.set_label(format!("Updated {}", image.display()).as_str())
+                    }
+                    Ok(WatchStatus::Removed(path)) => {
+                        status_label.set_label(format!("Removed {}", path.display()).as_str())
+                    }
+                    Ok(WatchStatus::NewDuplicate(image, duplicate)) => {
+                        let message =
+                            format!("{} is the same as {}", image.display(), duplicate.display());
+                        status_label.set_label(format!("New duplicate: {}", message).as_str());
+                        send_notification(&button, "New duplicate", &message);
+
+                        // nothing is shown, so the new pair can be reviewed right away
+                        if shown_pair.borrow().is_none() {
+                            executor::block_on(find_duplicates(
+                                left_image.clone(),
+                                left_image_label.clone(),
+                                right_image.clone(),
+                                right_image_label.clone(),
+                                &shown_pair,
+                                &remove_buttons,
+                            ))
+                            .unwrap();
+                        }
+                    }
+                    Err(err) => {
+                        button.set_active(false);
+                        status_label.set_label(format!("Can not watch folders: {}", err).as_str());
+                    }
+                }
+                Continue(true)
+            });
+        });
+    }
+
     fn handle_add_folders_btn(&self) {
         let blockable_widgets = Rc::new(self.get_blockable_widgets());
+        let folder_list = self.folder_list.clone();
         self.new_folder_chooser
             .connect_response(move |dialog, response| {
                 if response == gtk::ResponseType::Accept {
@@ -156,11 +611,10 @@ impl MainWindow {
                         .iter()
                         .for_each(|x| x.set_sensitive(false));
 
-                    let mut paths: Vec<String> = Vec::new();
+                    let mut paths: Vec<PathBuf> = Vec::new();
                     for item in dialog.files().iter() {
                         let item_path: gtk::gio::File = item.unwrap();
-                        let str = item_path.path().unwrap();
-                        paths.push(str.to_str().unwrap().to_string());
+                        paths.push(item_path.path().unwrap());
                     }
 
                     let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
@@ -173,10 +627,12 @@ impl MainWindow {
                     });
 
                     let blockable_widgets_clone = blockable_widgets.clone();
+                    let folder_list_clone = folder_list.clone();
                     receiver.attach(None, move |x| {
                         blockable_widgets_clone
                             .iter()
                             .for_each(|x| x.set_sensitive(true));
+                        folder_list_clone.refresh();
                         Continue(!x)
                     });
 
@@ -190,85 +646,161 @@ impl MainWindow {
     }
 
     fn handle_remove_left(&self) {
+        let strategy_dropdown = self.strategy_dropdown.clone();
+        let status_label = self.status_label.clone();
         let left_image = self.left_image.clone();
         let right_image = self.right_image.clone();
         let left_image_label = self.left_image_label.clone();
         let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
 
         self.remove_left_btn.connect_clicked(move |_| {
-            match (left_image.file(), right_image.file()) {
-                (Some(left_file), Some(right_file)) => executor::block_on(
-                    remove_and_protect_image(&right_file.to_string(), Some(&left_file.to_string())),
-                ),
-                (_, _) => {}
+            let pair = shown_pair.borrow().clone();
+            if let Some((left_file, right_file)) = pair {
+                let result = executor::block_on(remove_and_protect_image(
+                    &right_file,
+                    Some(&left_file),
+                    selected_strategy(&strategy_dropdown),
+                ));
+                if let Err(err) = result {
+                    status_label.set_label(format!("Can not remove image: {}", err).as_str());
+                    return;
+                }
             }
             executor::block_on(find_duplicates(
                 left_image.clone(),
                 left_image_label.clone(),
                 right_image.clone(),
                 right_image_label.clone(),
+                &shown_pair,
+                &remove_buttons,
             ))
             .unwrap();
         });
     }
 
     fn handle_save_both(&self) {
+        let status_label = self.status_label.clone();
         let left_image = self.left_image.clone();
         let right_image = self.right_image.clone();
         let left_image_label = self.left_image_label.clone();
         let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
 
         self.not_duplicates_btn.connect_clicked(move |_| {
-            match (left_image.file(), right_image.file()) {
-                (Some(left_file), Some(_)) => {
-                    executor::block_on(remove_and_protect_image(&left_file.to_string(), None))
+            let pair = shown_pair.borrow().clone();
+            if let Some((left_file, _)) = pair {
+                let result = executor::block_on(remove_and_protect_image(
+                    &left_file,
+                    None,
+                    ResolutionStrategy::default(),
+                ));
+                if let Err(err) = result {
+                    status_label.set_label(format!("Can not save both images: {}", err).as_str());
+                    return;
                 }
-                (_, _) => {}
             }
             executor::block_on(find_duplicates(
                 left_image.clone(),
                 left_image_label.clone(),
                 right_image.clone(),
                 right_image_label.clone(),
+                &shown_pair,
+                &remove_buttons,
             ))
             .unwrap();
         });
     }
 
     fn handle_remove_right(&self) {
+        let strategy_dropdown = self.strategy_dropdown.clone();
+        let status_label = self.status_label.clone();
         let left_image = self.left_image.clone();
         let right_image = self.right_image.clone();
         let left_image_label = self.left_image_label.clone();
         let right_image_label = self.right_image_label.clone();
+        let shown_pair = self.shown_pair.clone();
+        let remove_buttons = (self.remove_left_btn.clone(), self.remove_right_btn.clone());
 
         self.remove_right_btn.connect_clicked(move |_| {
-            match (left_image.file(), right_image.file()) {
-                (Some(left_file), Some(right_file)) => executor::block_on(
-                    remove_and_protect_image(&left_file.to_string(), Some(&right_file.to_string())),
-                ),
-                (_, _) => {}
+            let pair = shown_pair.borrow().clone();
+            if let Some((left_file, right_file)) = pair {
+                let result = executor::block_on(remove_and_protect_image(
+                    &left_file,
+                    Some(&right_file),
+                    selected_strategy(&strategy_dropdown),
+                ));
+                if let Err(err) = result {
+                    status_label.set_label(format!("Can not remove image: {}", err).as_str());
+                    return;
+                }
             }
             executor::block_on(find_duplicates(
                 left_image.clone(),
                 left_image_label.clone(),
                 right_image.clone(),
                 right_image_label.clone(),
+                &shown_pair,
+                &remove_buttons,
             ))
             .unwrap();
         });
     }
 
-    fn get_blockable_widgets(&self) -> Vec<impl WidgetExt> {
+    fn get_blockable_widgets(&self) -> Vec<gtk::Widget> {
         return vec![
-            self.remove_left_btn.clone(),
-            self.remove_right_btn.clone(),
-            self.not_duplicates_btn.clone(),
-            self.add_folder_btn.clone(),
-            self.scan_btn.clone(),
+            self.remove_left_btn.clone().upcast(),
+            self.remove_right_btn.clone().upcast(),
+            self.not_duplicates_btn.clone().upcast(),
+            self.strategy_dropdown.clone().upcast(),
+            self.add_folder_btn.clone().upcast(),
+            self.scan_btn.clone().upcast(),
+            self.auto_resolve_btn.clone().upcast(),
+            self.export_report_btn.clone().upcast(),
+            self.library_dropdown.clone().upcast(),
+            self.create_library_btn.clone().upcast(),
         ];
     }
 }
 
+pub(super) fn new_strategy_dropdown() -> gtk::DropDown {
+    let strategy_labels: Vec<&str> = ResolutionStrategy::ALL.iter().map(|x| x.label()).collect();
+
+    gtk::DropDown::from_strings(&strategy_labels)
+}
+
+pub(super) fn selected_strategy(dropdown: &gtk::DropDown) -> ResolutionStrategy {
+    ResolutionStrategy::ALL
+        .get(dropdown.selected() as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+// state of the last scan of the current library, empty before the first one
+fn last_scan_description() -> String {
+    match last_scan_session() {
+        Ok(Some(session)) => describe_session(&session),
+        Ok(None) => String::new(),
+        Err(err) => format!("Can not read the last scan: {}", err),
+    }
+}
+
+// desktop notification on behalf of the application which owns the widget
+fn send_notification(widget: &impl IsA<gtk::Widget>, title: &str, body: &str) {
+    let application = widget
+        .root()
+        .and_then(|x| x.downcast::<gtk::Window>().ok())
+        .and_then(|x| x.application());
+
+    if let Some(application) = application {
+        let notification = gtk::gio::Notification::new(title);
+        notification.set_body(Some(body));
+        application.send_notification(Some("new-duplicate"), &notification);
+    }
+}
+
 pub fn build_ui(app: &Application) {
     let main_window = MainWindow::new();
 
@@ -280,9 +812,29 @@ pub fn build_ui(app: &Application) {
         .orientation(gtk::Orientation::Vertical)
         .build();
 
+    let library_grid = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    library_grid.set_halign(gtk::Align::Center);
+    library_grid.append(&gtk::Label::new(Some("Library:")));
+    library_grid.append(&main_window.library_dropdown);
+    library_grid.append(&main_window.new_library_entry);
+    library_grid.append(&main_window.create_library_btn);
+    top_control_grid.append(&library_grid);
+
     top_control_grid.append(&main_window.add_folder_btn);
-    top_control_grid.append(&main_window.scan_btn);
+    let scan_grid = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    scan_grid.set_homogeneous(true);
+    main_window.scan_btn.set_hexpand(true);
+    scan_grid.append(&main_window.scan_btn);
+    scan_grid.append(&main_window.pause_scan_btn);
+    scan_grid.append(&main_window.cancel_scan_btn);
+    top_control_grid.append(&scan_grid);
+    top_control_grid.append(&main_window.watch_btn);
+    top_control_grid.append(&main_window.auto_resolve_btn);
+    top_control_grid.append(&main_window.export_report_btn);
+    top_control_grid.append(&main_window.folder_list.expander);
+    top_control_grid.append(&main_window.error_list.expander);
     top_control_grid.append(&main_window.status_label);
+    top_control_grid.append(&main_window.scan_progress);
     main_grid.append(&top_control_grid);
 
     let image_grid = gtk::Box::new(gtk::Orientation::Horizontal, 0);
@@ -310,11 +862,17 @@ pub fn build_ui(app: &Application) {
     buttons_grid.append(&main_window.remove_left_btn);
     buttons_grid.append(&main_window.not_duplicates_btn);
     buttons_grid.append(&main_window.remove_right_btn);
+    buttons_grid.append(&main_window.strategy_dropdown);
     main_grid.append(&buttons_grid);
 
     main_window
         .new_folder_chooser
         .set_transient_for(Some(&window));
+    main_window.report_chooser.set_transient_for(Some(&window));
+    main_window
+        .policy_window
+        .window
+        .set_transient_for(Some(&window));
     window.set_child(Some(&main_grid));
 
     window.show();
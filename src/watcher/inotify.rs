@@ -0,0 +1,174 @@
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::ptr;
+
+// enough for a few hundred events, the kernel never splits an event between reads
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// changes of files inside the folder, the folder itself is watched only for removal
+pub const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_ONLYDIR;
+
+pub struct Event {
+    pub watch: i32,
+    pub mask: u32,
+    // `None` for events of the watched folder itself
+    pub name: Option<OsString>,
+}
+
+// thin wrapper over the inotify file descriptor
+pub struct Inotify {
+    fd: i32,
+    buffer: Vec<u8>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd,
+            buffer: vec![0u8; BUFFER_SIZE],
+        })
+    }
+
+    // the same folder added twice returns the same watch
+    pub fn add_watch(&self, path: &Path) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let watch = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+        if watch < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(watch)
+    }
+
+    // watches of deleted folders are already gone, so failures are ignored
+    pub fn rm_watch(&self, watch: i32) {
+        unsafe { libc::inotify_rm_watch(self.fd, watch) };
+    }
+
+    // waits up to `timeout_ms` for events, returns nothing on timeout
+    pub fn read_events(&mut self, timeout_ms: i32) -> io::Result<Vec<Event>> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(err);
+        }
+        if ready == 0 {
+            return Ok(Vec::new());
+        }
+
+        let read = unsafe {
+            libc::read(
+                self.fd,
+                self.buffer.as_mut_ptr() as *mut libc::c_void,
+                self.buffer.len(),
+            )
+        };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Ok(Vec::new());
+            }
+            return Err(err);
+        }
+
+        Ok(parse_events(&self.buffer[..read as usize]))
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn parse_events(buffer: &[u8]) -> Vec<Event> {
+    let header_size = mem::size_of::<libc::inotify_event>();
+    let mut result = Vec::new();
+    let mut offset = 0;
+
+    while offset + header_size <= buffer.len() {
+        let header: libc::inotify_event =
+            unsafe { ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+        let name_start = offset + header_size;
+        let name_end = (name_start + header.len as usize).min(buffer.len());
+
+        // the name is padded with zero bytes
+        let name: Vec<u8> = buffer[name_start..name_end]
+            .iter()
+            .take_while(|x| **x != 0)
+            .copied()
+            .collect();
+
+        result.push(Event {
+            watch: header.wd,
+            mask: header.mask,
+            name: if name.is_empty() {
+                None
+            } else {
+                Some(OsString::from_vec(name))
+            },
+        });
+
+        offset = name_end;
+    }
+
+    result
+}
+
+#[test]
+fn test_inotify() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_path_buf();
+
+    let mut inotify = Inotify::new().unwrap();
+    let watch = inotify.add_watch(&root).unwrap();
+    assert_eq!(watch, inotify.add_watch(&root).unwrap());
+
+    std::fs::write(root.join("1.jpg"), "").unwrap();
+    std::fs::remove_file(root.join("1.jpg")).unwrap();
+
+    let mut events = Vec::new();
+    while events.len() < 3 {
+        let read = inotify.read_events(1000).unwrap();
+        assert!(!read.is_empty());
+        events.extend(read);
+    }
+
+    let masks: Vec<u32> = events.iter().map(|x| x.mask).collect();
+    assert_eq!(
+        vec![libc::IN_CREATE, libc::IN_CLOSE_WRITE, libc::IN_DELETE],
+        masks
+    );
+    assert!(events
+        .iter()
+        .all(|x| x.watch == watch && x.name == Some(OsString::from("1.jpg"))));
+}
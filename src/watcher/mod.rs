@@ -0,0 +1,374 @@
+mod inotify;
+
+use self::inotify::Event;
+use self::inotify::Inotify;
+use crate::archive;
+use crate::config;
+use crate::filesystem::ignore::is_ignored;
+use crate::filesystem::ignore::IgnoreRules;
+use crate::filesystem::is_correct_extension;
+use crate::filesystem::WalkOptions;
+use crate::hasher::try_image_hash;
+use crate::index::AcquiredConnection;
+use crate::scanner::walk_options;
+use crate::scanner::ScanOptions;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+// how often the stop flag is checked while nothing changes
+const POLL_INTERVAL_MS: i32 = 500;
+
+pub enum WatchStatus {
+    // all enabled folders are watched, with the number of watched subfolders
+    Watching(usize),
+    // image was added or its content was changed, the hash is already updated
+    ImageChanged(PathBuf),
+    // image or folder was deleted or moved away, its images are forgotten
+    Removed(PathBuf),
+    // the changed image and a not reviewed image with the same hash
+    NewDuplicate(PathBuf, PathBuf),
+}
+
+// watched folder with everything needed to check its files like a scan does
+struct WatchedFolder {
+    path: PathBuf,
+    rules: Vec<Arc<IgnoreRules>>,
+    options: Arc<WalkOptions>,
+    root_device: u64,
+}
+
+struct Watches {
+    inotify: Inotify,
+    folders: HashMap<i32, WatchedFolder>,
+}
+
+// watches enabled folders until `stop` is set: new and changed images are inserted
+// and hashed, deleted ones are removed from the index.
+// images which were already there are left for scans
+pub async fn watch_folders(
+    connection_pool: &mut AcquiredConnection,
+    scan_options: &ScanOptions,
+    stop: &AtomicBool,
+    on_status: impl Fn(WatchStatus),
+) -> io::Result<()> {
+    let global_excludes = config::global_excludes();
+    let folders = connection_pool
+        .get_all_folders()
+        .await
+        .map_err(io::Error::other)?;
+
+    let mut watches = Watches {
+        inotify: Inotify::new()?,
+        folders: HashMap::new(),
+    };
+
+    for folder in folders.iter().filter(|x| x.enabled) {
+        let root_device = match fs::metadata(&folder.path) {
+            Ok(metadata) => metadata.dev(),
+            Err(err) => {
+                log::warn!("Can not watch {}: {}", folder.path.display(), err);
+                continue;
+            }
+        };
+
+        let options = Arc::new(walk_options(folder, scan_options, &global_excludes));
+        let rules = vec![Arc::new(IgnoreRules::new(
+            &folder.path,
+            options.excludes.iter().map(|x| x.as_str()),
+        ))];
+
+        watches.add_tree(folder.path.clone(), rules, &options, root_device);
+    }
+
+    on_status(WatchStatus::Watching(watches.folders.len()));
+
+    while !stop.load(Ordering::Relaxed) {
+        for event in watches.inotify.read_events(POLL_INTERVAL_MS)? {
+            handle_event(connection_pool, &mut watches, event, &on_status).await;
+        }
+    }
+
+    Ok(())
+}
+
+impl Watches {
+    // watches the folder and all its subfolders, returns images found inside
+    fn add_tree(
+        &mut self,
+        path: PathBuf,
+        rules: Vec<Arc<IgnoreRules>>,
+        options: &Arc<WalkOptions>,
+        root_device: u64,
+    ) -> Vec<PathBuf> {
+        let mut images = Vec::new();
+        let mut folders = vec![(path, rules)];
+
+        while let Some((path, mut rules)) = folders.pop() {
+            let watch = match self.inotify.add_watch(&path) {
+                Ok(watch) => watch,
+                Err(err) if err.raw_os_error() == Some(libc::ENOSPC) => {
+                    log::warn!(
+                        "Can not watch {}: limit of watched folders is reached, \
+                         it can be raised with fs.inotify.max_user_watches",
+                        path.display()
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    log::warn!("Can not watch {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            // the same folder reached again, for example through a symlink
+            if self.folders.contains_key(&watch) {
+                continue;
+            }
+
+            rules.extend(IgnoreRules::load(&path).map(Arc::new));
+
+            let dir_data = match fs::read_dir(&path) {
+                Ok(dir_data) => dir_data,
+                Err(err) => {
+                    log::warn!("Can not read {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            for folder_value in dir_data.filter_map(|x| x.ok()) {
+                let folder_value_path = folder_value.path();
+
+                let is_symlink = folder_value
+                    .file_type()
+                    .map(|x| x.is_symlink())
+                    .unwrap_or(false);
+                if is_symlink && !options.follow_symlinks {
+                    continue;
+                }
+
+                let metadata = match fs::metadata(&folder_value_path) {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+
+                if is_ignored(&rules, &folder_value_path, metadata.is_dir()) {
+                    continue;
+                }
+
+                if metadata.is_dir() {
+                    if options.same_filesystem && metadata.dev() != root_device {
+                        continue;
+                    }
+                    folders.push((folder_value_path, rules.clone()));
+                } else if metadata.is_file()
+                    && is_correct_extension(&folder_value_path, &options.extensions)
+                {
+                    images.push(folder_value_path);
+                }
+            }
+
+            self.folders.insert(
+                watch,
+                WatchedFolder {
+                    path,
+                    rules,
+                    options: options.clone(),
+                    root_device,
+                },
+            );
+        }
+
+        images
+    }
+
+    // forgets the folder and its subfolders after they were deleted or moved away,
+    // a folder moved inside the tree is added again with its new path
+    fn remove_tree(&mut self, path: &Path) {
+        let watches: Vec<i32> = self
+            .folders
+            .iter()
+            .filter(|(_, folder)| folder.path.starts_with(path))
+            .map(|(watch, _)| *watch)
+            .collect();
+
+        for watch in watches {
+            self.folders.remove(&watch);
+            self.inotify.rm_watch(watch);
+        }
+    }
+}
+
+async fn handle_event(
+    connection_pool: &mut AcquiredConnection,
+    watches: &mut Watches,
+    event: Event,
+    on_status: &impl Fn(WatchStatus),
+) {
+    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+        log::warn!("Too many changes at once, some of them are missed until the next scan");
+        return;
+    }
+
+    // the watched folder itself was removed
+    if event.mask & libc::IN_IGNORED != 0 {
+        watches.folders.remove(&event.watch);
+        return;
+    }
+
+    let (folder, name) = match (watches.folders.get(&event.watch), event.name) {
+        (Some(folder), Some(name)) => (folder, name),
+        _ => return,
+    };
+    let path = folder.path.join(name);
+    let rules = folder.rules.clone();
+    let options = folder.options.clone();
+    let root_device = folder.root_device;
+    let is_dir = event.mask & libc::IN_ISDIR != 0;
+
+    if event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+        if is_dir {
+            watches.remove_tree(&path);
+        }
+        match connection_pool.remove_images(&path).await {
+            Ok(0) => {}
+            Ok(_) => on_status(WatchStatus::Removed(path)),
+            Err(err) => log::warn!("Can not remove {}: {}", path.display(), err),
+        }
+        return;
+    }
+
+    if is_ignored(&rules, &path, is_dir) {
+        return;
+    }
+
+    let images = if is_dir {
+        // moved folders may be already full of images
+        if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) == 0 {
+            return;
+        }
+        match fs::metadata(&path) {
+            Ok(metadata) if !options.same_filesystem || metadata.dev() == root_device => {}
+            _ => return,
+        }
+        watches.add_tree(path, rules, &options, root_device)
+    } else {
+        let is_symlink = fs::symlink_metadata(&path)
+            .map(|x| x.file_type().is_symlink())
+            .unwrap_or(false);
+        // symlinks are never written, so they are picked up when created
+        let is_changed = if is_symlink {
+            options.follow_symlinks && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+        } else {
+            event.mask & (libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) != 0
+        };
+
+        if !is_changed || !is_correct_extension(&path, &options.extensions) {
+            return;
+        }
+        vec![path]
+    };
+
+    for image in images {
+        if archive::is_archive(&image) {
+            update_archive(connection_pool, &image, &options.extensions, on_status).await;
+        } else {
+            let hash = try_image_hash(&image);
+            update_image(connection_pool, &image, hash, on_status).await;
+        }
+    }
+}
+
+// images inside a new or changed archive, the archive is read once
+async fn update_archive(
+    connection_pool: &mut AcquiredConnection,
+    path: &Path,
+    extensions: &[String],
+    on_status: &impl Fn(WatchStatus),
+) {
+    let images = match archive::hash_images(path, extensions) {
+        Ok(images) => images,
+        Err(err) => {
+            log::warn!("Can not read archive {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    // entries deleted from the archive are forgotten
+    let listed: Vec<PathBuf> = images.iter().map(|x| x.0.clone()).collect();
+    match connection_pool
+        .remove_missing_archive_images(path, &listed)
+        .await
+    {
+        Ok(removed) => {
+            for image in removed {
+                on_status(WatchStatus::Removed(image));
+            }
+        }
+        Err(err) => log::warn!("Can not remove old images of {}: {}", path.display(), err),
+    }
+
+    for (image, hash) in images {
+        update_image(connection_pool, &image, hash, on_status).await;
+    }
+}
+
+// inserts a new or changed image with its hash and looks for its duplicates,
+// failures are recorded like failures of scans
+async fn update_image(
+    connection_pool: &mut AcquiredConnection,
+    path: &Path,
+    hash: Result<i64, String>,
+    on_status: &impl Fn(WatchStatus),
+) {
+    // already known images keep their id, their hash is updated below
+    if let Err(err) = connection_pool.insert_image(path).await {
+        log::warn!("Can not insert {} into DB: {}", path.display(), err);
+        return;
+    }
+
+    let image = match connection_pool.get_image(path).await {
+        Ok(Some(image)) => image,
+        Ok(None) => return,
+        Err(err) => {
+            log::warn!("Can not read {} from DB: {}", path.display(), err);
+            return;
+        }
+    };
+
+    let hash = match connection_pool
+        .update_image_hashes(&[(image.id, hash.clone())])
+        .await
+    {
+        Ok(()) => hash.ok(),
+        Err(err) => {
+            log::warn!("Can not update hash of {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    on_status(WatchStatus::ImageChanged(path.to_path_buf()));
+
+    // closing a file without changes is not a new duplicate
+    if image.hash == hash {
+        return;
+    }
+
+    match connection_pool.get_duplicates_of(image.id).await {
+        Ok(duplicates) => {
+            if let Some(duplicate) = duplicates.into_iter().next() {
+                on_status(WatchStatus::NewDuplicate(
+                    path.to_path_buf(),
+                    duplicate.path,
+                ));
+            }
+        }
+        Err(err) => log::warn!("Can not find duplicates of {}: {}", path.display(), err),
+    }
+}
@@ -0,0 +1,601 @@
+use deduplicator::archive;
+use deduplicator::index::AcquiredConnection;
+use deduplicator::resolver;
+use deduplicator::resolver::policy::Policy;
+use deduplicator::scanner;
+use deduplicator::scanner::ScanControl;
+use deduplicator::scanner::ScanFolderStatus;
+use deduplicator::scanner::ScanOptions;
+use deduplicator::watcher;
+use deduplicator::watcher::WatchStatus;
+use deduplicator::Database;
+use deduplicator::ResolutionStrategy;
+use sqlx::Connection;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+// folder for images and the library database next to it,
+// removed when the test ends even if it fails
+struct TestLibrary {
+    dir: tempfile::TempDir,
+}
+
+impl TestLibrary {
+    fn new() -> Self {
+        let dir = tempfile::Builder::new()
+            .prefix("deduplicator-")
+            .tempdir()
+            .unwrap();
+        fs::create_dir(dir.path().join("images")).unwrap();
+
+        Self { dir }
+    }
+
+    fn root(&self) -> PathBuf {
+        self.dir.path().join("images")
+    }
+
+    fn database_path(&self) -> PathBuf {
+        self.dir.path().join("library.sqlite")
+    }
+
+    async fn connect(&self) -> AcquiredConnection {
+        let database = Database::open(&self.database_path()).await;
+        database.migrate().await;
+        database.get_connection().await
+    }
+}
+
+#[tokio::test]
+async fn test_scan_folders() {
+    let library = TestLibrary::new();
+    let mut connection = library.connect().await;
+
+    let folder = fs::canonicalize("tests/test_folders").unwrap();
+    let folders = connection.insert_folders(vec![folder]).await;
+    assert_eq!(1, folders.len());
+
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+
+    // test images are empty files
+    let stats = connection.get_stats().await.unwrap();
+    assert_eq!(5, stats.images);
+    assert_eq!(5, stats.failed);
+}
+
+#[tokio::test]
+async fn test_insert_images() {
+    let library = TestLibrary::new();
+    let mut connection = library.connect().await;
+
+    let paths = |names: &[&str]| -> Vec<PathBuf> {
+        names.iter().map(|x| Path::new("/photos").join(x)).collect()
+    };
+
+    let inserted = connection.insert_images(&paths(&["1.jpg", "2.jpg"])).await;
+    assert_eq!(paths(&["1.jpg", "2.jpg"]), inserted.unwrap());
+    // known images are skipped without errors
+    let inserted = connection.insert_images(&paths(&["2.jpg", "3.jpg"])).await;
+    assert_eq!(paths(&["3.jpg"]), inserted.unwrap());
+    assert!(!connection
+        .insert_image(Path::new("/photos/1.jpg"))
+        .await
+        .unwrap());
+
+    let images = connection.get_non_hashed_images(None).await.unwrap();
+    let hashes: Vec<(i64, Result<i64, String>)> = images
+        .iter()
+        .map(|x| match x.id {
+            1 => (x.id, Err("broken".to_string())),
+            _ => (x.id, Ok(x.id)),
+        })
+        .collect();
+    connection.update_image_hashes(&hashes).await.unwrap();
+    let stats = connection.get_stats().await.unwrap();
+    assert_eq!((2, 1), (stats.hashed, stats.failed));
+
+    // the broken image is skipped once it failed too many times
+    let failed = connection.get_failed_images().await.unwrap();
+    assert_eq!(1, failed.len());
+    assert_eq!(
+        ("broken", 1),
+        (failed[0].error.as_str(), failed[0].attempts)
+    );
+    assert_eq!(
+        1,
+        connection
+            .get_non_hashed_images(Some(2))
+            .await
+            .unwrap()
+            .len()
+    );
+    assert!(connection
+        .get_non_hashed_images(Some(1))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // a hashed image is not failed anymore
+    connection
+        .update_image_hashes(&[(failed[0].id, Ok(1))])
+        .await
+        .unwrap();
+    assert!(connection.get_failed_images().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_cancel_scan() {
+    let library = TestLibrary::new();
+    let mut connection = library.connect().await;
+
+    let folder = fs::canonicalize("tests/test_folders").unwrap();
+    connection.insert_folders(vec![folder]).await;
+
+    let control = ScanControl::default();
+    let cancelled = AtomicBool::new(false);
+    let hashed = AtomicBool::new(false);
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &control,
+        |status| match status {
+            ScanFolderStatus::ImageFound(_) => control.cancel(),
+            ScanFolderStatus::HashCalculated(_) => hashed.store(true, Ordering::Relaxed),
+            ScanFolderStatus::Cancelled => cancelled.store(true, Ordering::Relaxed),
+            _ => {}
+        },
+    )
+    .await;
+
+    // the walk was not finished, so the next scan walks the folder again
+    assert!(cancelled.load(Ordering::Relaxed));
+    let session = connection.get_last_scan_session().await.unwrap().unwrap();
+    assert_eq!("cancelled", session.phase);
+    assert!(session.walked_folders.is_empty());
+    assert!(!hashed.load(Ordering::Relaxed));
+    assert!(connection.get_stats().await.unwrap().images >= 1);
+    assert!(connection.get_all_folders().await.unwrap()[0]
+        .last_scan
+        .is_none());
+
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+    assert_eq!(5, connection.get_stats().await.unwrap().images);
+    assert!(connection.get_all_folders().await.unwrap()[0]
+        .last_scan
+        .is_some());
+
+    let resumed = connection.get_last_scan_session().await.unwrap().unwrap();
+    assert_eq!(session.id, resumed.id);
+    assert_eq!("done", resumed.phase);
+}
+
+#[tokio::test]
+async fn test_resume_scan() {
+    let library = TestLibrary::new();
+    let mut connection = library.connect().await;
+
+    let folder = fs::canonicalize("tests/test_folders").unwrap();
+    let folders = connection
+        .insert_folders(vec![folder.join("f1"), folder.join("f2")])
+        .await;
+
+    // the app died after the first folder was walked
+    let session_id = connection.insert_scan_session(0, "walking").await.unwrap();
+    connection
+        .add_scan_session_folders(session_id, &[folders[0].id, folders[1].id])
+        .await
+        .unwrap();
+    connection
+        .mark_scan_session_folder_walked(session_id, folders[0].id)
+        .await
+        .unwrap();
+
+    let resumed = AtomicBool::new(false);
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |status| {
+            if let ScanFolderStatus::Resumed(1) = status {
+                resumed.store(true, Ordering::Relaxed);
+            }
+        },
+    )
+    .await;
+
+    assert!(resumed.load(Ordering::Relaxed));
+    // only images of the second folder
+    assert_eq!(2, connection.get_stats().await.unwrap().images);
+    let session = connection.get_last_scan_session().await.unwrap().unwrap();
+    assert_eq!(session_id, session.id);
+    assert_eq!("done", session.phase);
+    assert_eq!(2, session.images_found);
+    assert_eq!(vec![folders[0].id, folders[1].id], session.walked_folders);
+}
+
+#[tokio::test]
+async fn test_remove_folder() {
+    let library = TestLibrary::new();
+    let mut connection = library.connect().await;
+
+    let folder = fs::canonicalize("tests/test_folders").unwrap();
+    let nested_folder = folder.join("f2");
+    let folders = connection
+        .insert_folders(vec![folder.clone(), nested_folder])
+        .await;
+
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+
+    let all_folders = connection.get_all_folders().await.unwrap();
+    assert!(all_folders.iter().all(|x| x.last_scan.is_some()));
+    assert_eq!(
+        vec![5, 2],
+        all_folders
+            .iter()
+            .map(|x| x.images_count)
+            .collect::<Vec<_>>()
+    );
+
+    // images of the nested folder are still covered by it
+    connection.remove_folder(folders[0].id).await.unwrap();
+    let stats = connection.get_stats().await.unwrap();
+    assert_eq!(1, stats.folders);
+    assert_eq!(2, stats.images);
+}
+
+#[test]
+fn test_watch_folders() {
+    let library = TestLibrary::new();
+    let root = library.root();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let watch_thread = {
+        let root = root.clone();
+        let database_path = library.database_path();
+        let stop = stop.clone();
+        thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let database = Database::open(&database_path).await;
+                database.migrate().await;
+                let mut connection = database.get_connection().await;
+                connection.insert_folders(vec![root]).await;
+
+                watcher::watch_folders(&mut connection, &ScanOptions::default(), &stop, |x| {
+                    sender.send(x).unwrap()
+                })
+                .await
+                .unwrap();
+            })
+        })
+    };
+    let next_status = || receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    assert!(matches!(next_status(), WatchStatus::Watching(1)));
+
+    let gradient =
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+    gradient.save(root.join("a.png")).unwrap();
+    assert!(matches!(next_status(), WatchStatus::ImageChanged(x) if x == root.join("a.png")));
+
+    // a copy inside a new folder
+    fs::create_dir(root.join("new")).unwrap();
+    fs::copy(root.join("a.png"), root.join("new/b.png")).unwrap();
+    loop {
+        match next_status() {
+            WatchStatus::NewDuplicate(left, right) => {
+                assert_eq!(root.join("new/b.png"), left);
+                assert_eq!(root.join("a.png"), right);
+                break;
+            }
+            WatchStatus::ImageChanged(_) => {}
+            _ => panic!("unexpected status"),
+        }
+    }
+
+    fs::remove_dir_all(root.join("new")).unwrap();
+    loop {
+        if let WatchStatus::Removed(path) = next_status() {
+            assert!(path.starts_with(root.join("new")));
+            break;
+        }
+    }
+
+    // failures are recorded like failures of scans
+    fs::write(root.join("broken.png"), "not an image").unwrap();
+    assert!(matches!(next_status(), WatchStatus::ImageChanged(x) if x == root.join("broken.png")));
+
+    stop.store(true, Ordering::Relaxed);
+    watch_thread.join().unwrap();
+
+    let failed = tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut connection = library.connect().await;
+        connection.get_failed_images().await.unwrap()
+    });
+    assert_eq!(1, failed.len());
+    assert_eq!(root.join("broken.png"), failed[0].path);
+    assert_eq!(1, failed[0].attempts);
+}
+
+#[test]
+fn test_watch_moved_folder() {
+    let library = TestLibrary::new();
+    let root = library.root();
+    fs::create_dir_all(root.join("old/sub")).unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let watch_thread = {
+        let root = root.clone();
+        let database_path = library.database_path();
+        let stop = stop.clone();
+        thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let database = Database::open(&database_path).await;
+                database.migrate().await;
+                let mut connection = database.get_connection().await;
+                connection.insert_folders(vec![root]).await;
+
+                watcher::watch_folders(&mut connection, &ScanOptions::default(), &stop, |x| {
+                    sender.send(x).unwrap()
+                })
+                .await
+                .unwrap();
+            })
+        })
+    };
+    let next_status = || receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    assert!(matches!(next_status(), WatchStatus::Watching(3)));
+
+    let gradient =
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+    gradient.save(root.join("old/sub/a.png")).unwrap();
+    assert!(
+        matches!(next_status(), WatchStatus::ImageChanged(x) if x == root.join("old/sub/a.png"))
+    );
+
+    // images of the moved folder are found under the new path
+    fs::rename(root.join("old"), root.join("moved")).unwrap();
+    assert!(matches!(next_status(), WatchStatus::Removed(x) if x == root.join("old")));
+    assert!(
+        matches!(next_status(), WatchStatus::ImageChanged(x) if x == root.join("moved/sub/a.png"))
+    );
+
+    // and its subfolders are still watched
+    gradient.save(root.join("moved/sub/b.png")).unwrap();
+    loop {
+        match next_status() {
+            WatchStatus::NewDuplicate(left, right) => {
+                assert_eq!(root.join("moved/sub/b.png"), left);
+                assert_eq!(root.join("moved/sub/a.png"), right);
+                break;
+            }
+            WatchStatus::ImageChanged(x) => assert_eq!(root.join("moved/sub/b.png"), x),
+            _ => panic!("unexpected status"),
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    watch_thread.join().unwrap();
+}
+
+#[tokio::test]
+async fn test_scan_archives() {
+    let library = TestLibrary::new();
+    let root = library.root();
+
+    let gradient =
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+    gradient.save(root.join("a.png")).unwrap();
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(root.join("backup.zip")).unwrap());
+    zip.start_file("old/a.png", zip::write::FileOptions::default())
+        .unwrap();
+    zip.write_all(&fs::read(root.join("a.png")).unwrap())
+        .unwrap();
+    zip.finish().unwrap();
+    // the archive is older, so it is kept
+    fs::File::options()
+        .write(true)
+        .open(root.join("backup.zip"))
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(3600))
+        .unwrap();
+
+    let mut connection = library.connect().await;
+    connection.insert_folders(vec![root.clone()]).await;
+
+    // images inside archives are hashed by the pool like other images
+    let hashed = std::cell::Cell::new(0);
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |status| {
+            if let ScanFolderStatus::Progress(progress) = status {
+                hashed.set(progress.hashed);
+            }
+        },
+    )
+    .await;
+    assert_eq!(2, hashed.get());
+
+    let archived = archive::virtual_path(&root.join("backup.zip"), Path::new("old/a.png"));
+    let groups = connection.get_duplicate_groups().await.unwrap();
+    assert_eq!(1, groups.len());
+    let mut paths: Vec<_> = groups[0].iter().map(|x| x.path.clone()).collect();
+    paths.sort();
+    assert_eq!(vec![root.join("a.png"), archived.clone()], paths);
+
+    // the copy inside archive is read-only, only the loose copy can be deleted
+    let plan = Policy::recommended().plan(groups.clone());
+    assert_eq!(1, plan.len());
+    assert_eq!(
+        (&archived, &root.join("a.png")),
+        (&plan[0].keep, &plan[0].remove)
+    );
+    let policy = Policy {
+        strategy: ResolutionStrategy::Hardlink,
+        ..Policy::recommended()
+    };
+    assert!(policy.plan(groups).is_empty());
+    assert!(resolver::resolve_duplicate(
+        &root.join("a.png"),
+        &archived,
+        ResolutionStrategy::Delete,
+        true
+    )
+    .is_err());
+    assert!(resolver::resolve_duplicate(
+        &archived,
+        &root.join("a.png"),
+        ResolutionStrategy::Delete,
+        true
+    )
+    .is_ok());
+
+    // the image was moved inside the changed archive
+    let mut zip = zip::ZipWriter::new(fs::File::create(root.join("backup.zip")).unwrap());
+    zip.start_file("new/a.png", zip::write::FileOptions::default())
+        .unwrap();
+    zip.write_all(&fs::read(root.join("a.png")).unwrap())
+        .unwrap();
+    zip.finish().unwrap();
+
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+
+    assert!(connection.get_image(&archived).await.unwrap().is_none());
+    let moved = archive::virtual_path(&root.join("backup.zip"), Path::new("new/a.png"));
+    let groups = connection.get_duplicate_groups().await.unwrap();
+    assert_eq!(1, groups.len());
+    let mut paths: Vec<_> = groups[0].iter().map(|x| x.path.clone()).collect();
+    paths.sort();
+    assert_eq!(vec![root.join("a.png"), moved], paths);
+}
+
+#[tokio::test]
+async fn test_jpeg_and_png_copies() {
+    let library = TestLibrary::new();
+    let root = library.root();
+
+    // jpeg images are decoded at reduced size, the copy in png at full size
+    let picture = image::RgbImage::from_fn(640, 480, |x, y| {
+        image::Rgb([(x * 255 / 640) as u8, (y * 255 / 480) as u8, 128])
+    });
+    picture.save(root.join("a.png")).unwrap();
+    picture.save(root.join("a.jpg")).unwrap();
+
+    let mut connection = library.connect().await;
+    connection.insert_folders(vec![root.clone()]).await;
+
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+
+    let groups = connection.get_duplicate_groups().await.unwrap();
+    assert_eq!(1, groups.len());
+    let mut paths: Vec<_> = groups[0].iter().map(|x| x.path.clone()).collect();
+    paths.sort();
+    assert_eq!(vec![root.join("a.jpg"), root.join("a.png")], paths);
+}
+
+#[tokio::test]
+async fn test_database_failure() {
+    let library = TestLibrary::new();
+    let root = library.root();
+    let gradient =
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+    gradient.save(root.join("a.png")).unwrap();
+
+    let mut connection = library.connect().await;
+    connection.insert_folders(vec![root.clone()]).await;
+
+    // another writer holds the database while hashes are stored
+    let url = format!("sqlite://{}", library.database_path().display());
+    let mut other = sqlx::SqliteConnection::connect(&url).await.unwrap();
+    sqlx::query(
+        "CREATE TRIGGER locked BEFORE UPDATE OF hash ON images
+        BEGIN SELECT RAISE(ABORT, 'database is locked'); END",
+    )
+    .execute(&mut other)
+    .await
+    .unwrap();
+
+    let failed = std::cell::RefCell::new(None);
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |status| match status {
+            ScanFolderStatus::Failed(err) => *failed.borrow_mut() = Some(err),
+            ScanFolderStatus::Done => panic!("the scan should stop"),
+            _ => {}
+        },
+    )
+    .await;
+    assert!(failed.take().unwrap().contains("database is locked"));
+    assert_eq!(0, connection.get_stats().await.unwrap().hashed);
+
+    // the next scan continues
+    sqlx::query("DROP TRIGGER locked")
+        .execute(&mut other)
+        .await
+        .unwrap();
+    scanner::scan_folders(
+        &mut connection,
+        &ScanOptions::default(),
+        &ScanControl::default(),
+        |_| {},
+    )
+    .await;
+    assert_eq!(1, connection.get_stats().await.unwrap().hashed);
+    assert_eq!(
+        "done",
+        connection
+            .get_last_scan_session()
+            .await
+            .unwrap()
+            .unwrap()
+            .phase
+    );
+}